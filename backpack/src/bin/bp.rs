@@ -1,4 +1,5 @@
 mod commands;
+use backpack::Error;
 use std::process::exit;
 use tracing_subscriber::{filter, EnvFilter, Registry};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -17,7 +18,8 @@ fn main() {
     let app = commands::root::command()
         .subcommand(commands::cache::command())
         .subcommand(commands::add::command())
-        .subcommand(commands::config::command());
+        .subcommand(commands::config::command())
+        .subcommand(commands::update::command());
 
     let matches = app.clone().get_matches();
 
@@ -27,6 +29,7 @@ fn main() {
             ("cache", subcommand_matches) => commands::cache::run(&matches, subcommand_matches),
             ("add", subcommand_matches) => commands::add::run(&matches, subcommand_matches),
             ("config", subcommand_matches) => commands::config::run(&matches, subcommand_matches),
+            ("update", subcommand_matches) => commands::update::run(&matches, subcommand_matches),
             (maybe_shortlink, _) => {
                 unreachable!("unexpected subcommand: {}", maybe_shortlink);
             }
@@ -39,7 +42,24 @@ fn main() {
         }
         Err(err) => {
             eprintln!("error: {err}");
-            exit(1)
+            exit(exit_code(&err))
         }
     }
 }
+
+/// Exit codes for the failures a script may want to act on, see README
+fn exit_code(err: &anyhow::Error) -> i32 {
+    match err.downcast_ref::<Error>() {
+        Some(Error::Config { .. }) => 3,
+        Some(Error::RepoNotFound(_)) => 4,
+        Some(Error::AuthFailed(_)) => 5,
+        Some(Error::NoSuchRef(_)) => 6,
+        Some(Error::MissingVars(_)) => 7,
+        Some(Error::Headless(_)) => 8,
+        Some(Error::Conflict(_)) => 9,
+        Some(Error::Blocked { .. }) => 10,
+        Some(Error::UnsafePath { .. }) => 11,
+        Some(Error::Cancelled) => 130,
+        Some(Error::Io(_) | Error::Other(_)) | None => 1,
+    }
+}
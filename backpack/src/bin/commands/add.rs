@@ -1,5 +1,4 @@
 use std::collections::BTreeMap;
-use std::collections::HashMap;
 use std::path::Path;
 
 use anyhow::Context;
@@ -7,9 +6,11 @@ use anyhow::Result as AnyResult;
 use backpack::config::LocalProjectConfig;
 use backpack::config::Project;
 use backpack::config::PROJECT_CONFIG_FILE;
-use backpack::git::GitCmd;
-use backpack::git::GitProvider;
-use backpack::{config::Config, ui::Prompt};
+use backpack::git;
+use backpack::{
+    config::Config,
+    ui::{Prompt, Prompter},
+};
 use clap::{Arg, ArgMatches, Command};
 
 pub fn command() -> Command<'static> {
@@ -46,7 +47,7 @@ pub fn run(_matches: &ArgMatches, subcommand_matches: &ArgMatches) -> AnyResult<
     } else {
         let repo = subcommand_matches
             .get_one::<String>("repo")
-            .map_or_else(|| GitCmd::default().get_local_url(), |r| Ok(r.to_string()))?;
+            .map_or_else(|| git::provider().get_local_url(), |r| Ok(r.to_string()))?;
 
         let name = prompt.ask_for_project_name(&repo)?;
         // add it to the configuration and save
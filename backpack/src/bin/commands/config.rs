@@ -28,11 +28,22 @@ pub fn run(_matches: &ArgMatches, subcommand_matches: &ArgMatches) -> AnyResult<
         let generated = Config::init_global()?;
         println!("wrote: {}.", generated.display());
     } else {
-        let global = Config::global_config_file()?;
-        print_path("global", global.as_path());
+        for (kind, path) in Config::config_files() {
+            print_path(kind, path.as_path());
+        }
 
-        let t = Config::load_or_default()?.to_text()?;
-        println!("{t}");
+        let config = Config::load_or_default()?;
+        println!("{}", config.to_text()?);
+
+        println!("effective policy:");
+        print!("{}", config.policy.clone().unwrap_or_default());
+
+        if !config.project_files.is_empty() {
+            println!("project sources:");
+            for (name, path) in &config.project_files {
+                println!("  {}: {}", name, path.display());
+            }
+        }
     }
 
     Ok(true)
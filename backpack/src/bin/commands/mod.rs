@@ -2,3 +2,4 @@ pub mod add;
 pub mod cache;
 pub mod config;
 pub mod root;
+pub mod update;
@@ -1,12 +1,18 @@
 use anyhow::Result;
 use backpack::data::CopyMode;
+use backpack::data::OnConflict;
 use backpack::data::Opts;
+use backpack::data::Transport;
 use backpack::run::Runner;
+use backpack::vars;
 use clap::Arg;
 use clap::ArgMatches;
 //use anyhow::Result as AnyResult;
 use clap::crate_version;
 use clap::Command;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
 
 pub fn command() -> Command<'static> {
     Command::new("backpack")
@@ -21,6 +27,14 @@ pub fn command() -> Command<'static> {
                 .help("Clone with git")
                 .takes_value(false),
         )
+        .arg(
+            Arg::new("transport")
+                .short('t')
+                .long("transport")
+                .help("Transport to clone with, overriding configuration")
+                .possible_values(["ssh", "https"])
+                .takes_value(true),
+        )
         .arg(
             Arg::new("overwrite")
                 .short('w')
@@ -42,6 +56,72 @@ pub fn command() -> Command<'static> {
                 .help("Fetch resources without using the cache")
                 .takes_value(false),
         )
+        .arg(
+            Arg::new("merge")
+                .long("merge")
+                .help("Merge template changes into existing files, keeping your edits")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Show what would be generated, without writing files or running actions")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print a JSON report of what was generated")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Generate without asking for confirmation")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("headless")
+                .long("headless")
+                .help("Never prompt: fail instead of asking anything (implies --non-interactive)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("on-conflict")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .help("What to do with existing files, instead of asking")
+                .possible_values(["overwrite", "skip", "fail", "backup"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("trust")
+                .long("trust")
+                .help("Run the template's own actions without asking to trust it")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .value_name("KEY=VALUE")
+                .help("Answer a question up front (repeatable)")
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("vars-file")
+                .long("vars-file")
+                .value_name("FILE")
+                .help("Answer questions up front from a YAML file of key: value")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("non-interactive")
+                .long("non-interactive")
+                .help("Fail listing the questions that have no answer, instead of asking")
+                .takes_value(false),
+        )
         .arg(
             Arg::new("config")
                 .short('c')
@@ -57,25 +137,63 @@ pub fn run(matches: &ArgMatches) -> Result<bool> {
     let shortlink = matches.get_one::<String>("shortlink");
     let dest = matches.get_one::<String>("dest");
     let config_file = matches.get_one::<String>("config").map(String::to_string);
+    let transport = matches
+        .get_one::<String>("transport")
+        .map(|t| Transport::from_str(t))
+        .transpose()?;
     let mode = if matches.is_present("fetch") {
         CopyMode::Apply
     } else {
         CopyMode::Copy
     };
+    let json = matches.is_present("json");
     let r = Runner::default();
-    r.run(
+    let report = r.run(
         shortlink.map(String::as_str),
         dest.map(String::as_str),
         &Opts {
-            show_progress: true,
+            show_progress: !json,
             overwrite: matches.is_present("overwrite"),
             is_git: matches.is_present("git"),
             no_cache: matches.is_present("no-cache"),
-            always_yes: false,
+            always_yes: matches.is_present("yes"),
             config_file,
             mode,
+            transport,
+            dry_run: matches.is_present("dry-run"),
+            merge: matches.is_present("merge"),
+            vars: answers(matches)?,
+            non_interactive: matches.is_present("non-interactive"),
+            headless: matches.is_present("headless"),
+            on_conflict: on_conflict(matches)?,
+            trust: matches.is_present("trust"),
         },
     )?;
 
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
     Ok(true)
 }
+
+/// The `--on-conflict` policy, if one was given
+pub fn on_conflict(matches: &ArgMatches) -> Result<Option<OnConflict>> {
+    matches
+        .get_one::<String>("on-conflict")
+        .map(|c| OnConflict::from_str(c))
+        .transpose()
+}
+
+/// Answers given with `--vars-file`, then `--var`, so that `--var` wins
+pub fn answers(matches: &ArgMatches) -> Result<BTreeMap<String, String>> {
+    let mut vars = matches
+        .get_one::<String>("vars-file")
+        .map(|file| vars::load_file(Path::new(file)))
+        .transpose()?
+        .unwrap_or_default();
+    for assignment in matches.get_many::<String>("var").into_iter().flatten() {
+        let (key, val) = vars::parse_assignment(assignment)?;
+        vars.insert(key, val);
+    }
+    Ok(vars)
+}
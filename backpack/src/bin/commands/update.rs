@@ -0,0 +1,60 @@
+use super::root;
+use anyhow::Result as AnyResult;
+use backpack::data::{CopyMode, Opts};
+use backpack::run::Runner;
+use clap::{Arg, ArgMatches, Command};
+
+pub fn command() -> Command<'static> {
+    Command::new("update")
+        .about("Update a generated folder from the latest of what it was generated from")
+        .arg(
+            Arg::new("merge")
+                .long("merge")
+                .help("Merge template changes into files you edited, instead of leaving them")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print a JSON report of what was updated")
+                .takes_value(false),
+        )
+        .arg(Arg::new("dest").help("A folder generated by backpack (default: current folder)"))
+}
+
+pub fn run(matches: &ArgMatches, subcommand_matches: &ArgMatches) -> AnyResult<bool> {
+    let json = subcommand_matches.is_present("json");
+    let report = Runner::default().update(
+        subcommand_matches
+            .get_one::<String>("dest")
+            .map(String::as_str),
+        &Opts {
+            show_progress: !json,
+            overwrite: false,
+            // how to fetch is replayed from the manifest
+            is_git: false,
+            no_cache: true,
+            always_yes: matches.is_present("yes"),
+            config_file: matches.get_one::<String>("config").map(String::to_string),
+            mode: CopyMode::Apply,
+            transport: None,
+            dry_run: false,
+            merge: subcommand_matches.is_present("merge"),
+            vars: root::answers(matches)?,
+            non_interactive: matches.is_present("non-interactive"),
+            headless: matches.is_present("headless"),
+            on_conflict: root::on_conflict(matches)?,
+            trust: matches.is_present("trust"),
+        },
+    )?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if !report.kept.is_empty() {
+        println!("left as you changed them:");
+        for file in &report.kept {
+            println!("  {}", file.display());
+        }
+    }
+    Ok(true)
+}
@@ -1,5 +1,6 @@
-use crate::templates::Swap;
-use anyhow::{anyhow, bail, Context, Result as AnyResult};
+use crate::data::Transport;
+use crate::templates::{self, Swap};
+use crate::{Error, Result};
 use dirs;
 use interactive_actions::data::Action;
 use serde_derive::{Deserialize, Serialize};
@@ -9,6 +10,7 @@ use std::{env, fs};
 
 const GLOBAL_CONFIG_FOLDER: &str = ".backpack";
 const GLOBAL_CONFIG_FILE: &str = "backpack.yaml";
+const LOCAL_CONFIG_FILE: &str = "backpack.yaml";
 pub const PROJECT_CONFIG_FILE: &str = ".backpack-project.yaml";
 const CONFIG_TEMPLATE: &str = r###"
 
@@ -25,6 +27,7 @@ version: 1
 #   rust-starter: 
 #     shortlink: jondot/rust-starter  # you can use any custom prefix here too
 #     # is_git: true # force fetch from ssh
+#     # transport: https # clone with https instead of ssh (ssh | https)
 #
 
 #
@@ -37,7 +40,10 @@ version: 1
 #   # $ backpack my-org/my-repo
 #   default: 
 #     kind: gitlab # options: gitlab | github | bitbucket
-#     base: my.gitlab.com
+#     base: my.gitlab.com # may include a port, e.g. my.gitlab.com:8443
+#     transport: https # options: ssh | https
+#     # ssh_port: 2222 # when ssh isn't served on the default port
+#     # ssh_user: git # the user to connect with over ssh
 #   custom:
 #     # custom github org to prefix, and also overrides the 'gh:' prefix.
 #     # $ backpack new gh:my-repo my-repo
@@ -50,6 +56,29 @@ version: 1
 #     ghe:
 #       kind: github
 #       base: github.enterprise.example.com
+
+#
+# templates can ship actions of their own, which run commands only once you
+# trusted the template at its revision with those commands (or with --trust).
+# list hosts here to never run actions of templates from any other host.
+#
+# trust:
+#   hosts:
+#     - github.com
+#     - my.gitlab.com
+
+#
+# only generate from hosts and vendors you allow, and never from ones you deny.
+# a local backpack.yaml can only make this stricter.
+#
+# policy:
+#   allow:
+#     domains:
+#       - github.com
+#       - "*.my-org.com" # any subdomain
+#   deny:
+#     vendors:
+#       - gist
 "###;
 
 pub type ProjectMap = BTreeMap<String, Project>;
@@ -61,34 +90,134 @@ pub struct Config {
 
     #[serde(rename = "vendors", default)]
     pub vendors: Option<VendorsConfig>,
+
+    #[serde(rename = "trust", default)]
+    pub trust: Option<TrustConfig>,
+
+    #[serde(rename = "policy", default)]
+    pub policy: Option<Policy>,
+
+    /// which file each project was loaded from
+    #[serde(skip)]
+    pub project_files: BTreeMap<String, PathBuf>,
 }
 
 impl Config {
     #[tracing::instrument(name = "config_from_text", skip_all, err)]
-    pub fn from_text(text: &str) -> AnyResult<Self> {
+    pub fn from_text(text: &str) -> Result<Self> {
         let conf: Self = serde_yaml::from_str(text)?;
         Ok(conf)
     }
     #[tracing::instrument(name = "config_from_text", skip_all, err)]
-    pub fn to_text(&self) -> AnyResult<String> {
-        serde_yaml::to_string(self).context("cannot parse YAML")
+    pub fn to_text(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
     }
 
     #[tracing::instrument(name = "config_load", skip_all, err)]
-    pub fn load(text: &str) -> AnyResult<Self> {
+    pub fn load(text: &str) -> Result<Self> {
         Self::from_text(text)
     }
 
     #[tracing::instrument(name = "config_path", skip_all, err)]
-    pub fn from_path(file: &Path) -> AnyResult<Self> {
-        Self::from_text(&fs::read_to_string(file)?)
+    pub fn from_path(file: &Path) -> Result<Self> {
+        let mut config =
+            Self::from_text(&fs::read_to_string(file)?).map_err(|e| e.in_file(file))?;
+        config.project_files = config
+            .projects
+            .iter()
+            .flat_map(|projects| projects.keys())
+            .map(|name| (name.clone(), file.to_path_buf()))
+            .collect();
+        Ok(config)
     }
 
+    /// Load and merge every configuration file that exists, see [`Config::config_files`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an existing file cannot be read or parsed
     #[tracing::instrument(name = "config_load", skip_all, err)]
-    pub fn load_or_default() -> AnyResult<Self> {
-        Ok(Self::global_config_file()
-            .and_then(|c| Self::from_path(&c))
-            .unwrap_or_default())
+    pub fn load_or_default() -> Result<Self> {
+        let mut seen = vec![];
+        let mut config = Self::default();
+        for (_, file) in Self::config_files() {
+            if !file.is_file() {
+                continue;
+            }
+            // the same file may show up in more than one layer, e.g. `BP_CONF` pointing at the local file
+            let canonical = file.canonicalize()?;
+            if seen.contains(&canonical) {
+                continue;
+            }
+            seen.push(canonical);
+            config = config.merge(Self::from_path(&file)?)?;
+        }
+        Ok(config)
+    }
+
+    /// The configuration files to load, lowest precedence first:
+    ///
+    /// 1. `global`: `~/.backpack/backpack.yaml` (or in `BP_FOLDER`)
+    /// 2. `local`: the nearest `backpack.yaml` walking up from the current directory
+    /// 3. `BP_CONF`: a file given in the `BP_CONF` environment variable
+    ///
+    /// Files that don't exist are listed too, so they can be shown to the user.
+    pub fn config_files() -> Vec<(&'static str, PathBuf)> {
+        let mut files = vec![];
+        if let Ok(global) = Self::global_config_file() {
+            files.push(("global", global));
+        }
+        if let Some(local) = env::current_dir()
+            .ok()
+            .and_then(|cwd| Self::local_config_file(&cwd))
+        {
+            files.push(("local", local));
+        }
+        if let Ok(conf) = env::var("BP_CONF") {
+            files.push(("BP_CONF", PathBuf::from(conf)));
+        }
+        files
+    }
+
+    /// Find the nearest `backpack.yaml` in `dir` or any of its parents
+    pub fn local_config_file(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(LOCAL_CONFIG_FILE))
+            .find(|f| f.is_file())
+    }
+
+    /// Layer `other` on top of this configuration. Projects are replaced whole by name,
+    /// so actions and swaps of two layers never mix; vendors are merged field by field.
+    /// The trust and fetch policies can only get stricter, see [`TrustConfig::merge`]
+    /// and [`Policy::merge`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the vendors cannot be merged
+    pub fn merge(mut self, other: Self) -> Result<Self> {
+        if let Some(projects) = other.projects {
+            self.projects
+                .get_or_insert_with(ProjectMap::new)
+                .extend(projects);
+        }
+        self.project_files.extend(other.project_files);
+
+        self.vendors = match (self.vendors, other.vendors) {
+            (Some(base), Some(overrides)) => Some(
+                merge_struct::merge(&base, &overrides)
+                    .map_err(|e| Error::config(format!("cannot merge vendors: {e}")))?,
+            ),
+            (base, overrides) => overrides.or(base),
+        };
+        self.trust = match (self.trust, other.trust) {
+            (Some(base), Some(overrides)) => Some(base.merge(overrides)),
+            (base, overrides) => overrides.or(base),
+        };
+        self.policy = match (self.policy, other.policy) {
+            (Some(base), Some(overrides)) => Some(base.merge(overrides)),
+            (base, overrides) => overrides.or(base),
+        };
+        Ok(self)
     }
 
     /// Return a user's home directory
@@ -96,13 +225,13 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if it cannot get the home dir
-    pub fn global_config_folder() -> AnyResult<PathBuf> {
+    pub fn global_config_folder() -> Result<PathBuf> {
         // change config dir to be user home
         if let Ok(env_global_folder) = env::var("BP_FOLDER") {
             Ok(PathBuf::from(env_global_folder))
         } else {
             Ok(dirs::home_dir()
-                .ok_or_else(|| anyhow!("no home dir found"))?
+                .ok_or_else(|| Error::config("no home dir found"))?
                 .join(GLOBAL_CONFIG_FOLDER))
         }
     }
@@ -112,7 +241,7 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if there's no home directory
-    pub fn global_config_file() -> AnyResult<PathBuf> {
+    pub fn global_config_file() -> Result<PathBuf> {
         Self::global_config_folder().map(|c| c.join(GLOBAL_CONFIG_FILE))
     }
 
@@ -121,7 +250,7 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if there's no home directory
-    pub fn global_cache_folder() -> AnyResult<PathBuf> {
+    pub fn global_cache_folder() -> Result<PathBuf> {
         Self::global_config_folder().map(|c| c.join("cache"))
     }
 
@@ -130,7 +259,7 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if it cannot create a global configuration
-    pub fn init_global() -> AnyResult<PathBuf> {
+    pub fn init_global() -> Result<PathBuf> {
         let folder = Self::global_config_folder()?;
         fs::create_dir_all(folder)?;
         let path = Self::global_config_file()?;
@@ -143,7 +272,7 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if cannot save to disk
-    pub fn save(&self) -> AnyResult<()> {
+    pub fn save(&self) -> Result<()> {
         self.save_to(&Config::global_config_file()?)?;
         Ok(())
     }
@@ -153,14 +282,17 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if cannot save to disk
-    pub fn save_to(&self, file: &Path) -> AnyResult<()> {
+    pub fn save_to(&self, file: &Path) -> Result<()> {
         fs::write(file, serde_yaml::to_string(self)?)?;
         Ok(())
     }
 
-    fn init_to(path: &Path) -> AnyResult<()> {
+    fn init_to(path: &Path) -> Result<()> {
         if path.exists() {
-            bail!("configuration file already exists: {}", path.display());
+            return Err(Error::Config {
+                path: Some(path.to_path_buf()),
+                message: "configuration file already exists".to_string(),
+            });
         }
         fs::write(path, CONFIG_TEMPLATE)?;
         Ok(())
@@ -191,7 +323,7 @@ impl LocalProjectConfig {
     /// # Errors
     ///
     /// This function will return an error if IO fails
-    pub fn from_text(text: &str) -> AnyResult<Self> {
+    pub fn from_text(text: &str) -> Result<Self> {
         let conf: Self = serde_yaml::from_str(text)?;
         Ok(conf)
     }
@@ -201,13 +333,29 @@ impl LocalProjectConfig {
     /// # Errors
     ///
     /// This function will return an error if .
-    pub fn from_path(path: &Path) -> AnyResult<Option<Self>> {
+    pub fn from_path(path: &Path) -> Result<Option<Self>> {
         if path.exists() {
-            Ok(Some(Self::from_text(&fs::read_to_string(path)?)?))
+            Ok(Some(
+                Self::from_text(&fs::read_to_string(path)?).map_err(|e| e.in_file(path))?,
+            ))
         } else {
             Ok(None)
         }
     }
+
+    /// Load the first local project definition found in `folders`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a found file cannot be read or parsed
+    pub fn find_in(folders: &[&Path]) -> Result<Option<Self>> {
+        for folder in folders.iter().filter(|f| f.is_dir()) {
+            if let Some(local) = Self::from_path(&folder.join(PROJECT_CONFIG_FILE))? {
+                return Ok(Some(local));
+            }
+        }
+        Ok(None)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -219,17 +367,46 @@ pub struct Project {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_git: Option<bool>,
 
+    #[serde(rename = "transport")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+
     #[serde(rename = "description")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     #[serde(rename = "actions")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub actions: Option<Vec<Action>>,
+    pub actions: Option<Vec<ProjectAction>>,
 
     #[serde(rename = "swaps")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub swaps: Option<Vec<Swap>>,
+
+    /// gitignore-style patterns of files not to copy
+    #[serde(rename = "exclude")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+
+    /// gitignore-style patterns of files to copy even if excluded
+    #[serde(rename = "include")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+
+    /// glob patterns of files to render as Tera templates
+    #[serde(rename = "render")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render: Option<Vec<String>>,
+
+    /// files copied only when a condition over the vars holds
+    #[serde(rename = "optional")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<Vec<OptionalPath>>,
 }
 impl Project {
     pub fn from_link(ln: &str) -> Self {
@@ -240,13 +417,364 @@ impl Project {
     }
 }
 
+/// An action, which runs only if its `if:` condition holds over the vars collected
+/// by the time it's reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectAction {
+    #[serde(flatten)]
+    pub action: Action,
+
+    /// a Tera expression, e.g. `use_docker == 'yes'`
+    #[serde(rename = "if")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+}
+
+impl ProjectAction {
+    /// Should this action run, given the vars so far?
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the condition is illegal, or uses a variable that isn't set
+    pub fn applies(&self, vars: &BTreeMap<String, String>) -> Result<bool> {
+        self.condition.as_ref().map_or(Ok(true), |condition| {
+            templates::eval_condition(condition, vars)
+                .map_err(|err| Error::config(format!("in action '{}': {}", self.action.name, err)))
+        })
+    }
+}
+
+impl From<Action> for ProjectAction {
+    fn from(action: Action) -> Self {
+        Self {
+            action,
+            condition: None,
+        }
+    }
+}
+
+/// Files and folders (a gitignore-style pattern) that are copied only when a
+/// condition over the vars holds, e.g. `web/` when `with_frontend == 'yes'`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionalPath {
+    #[serde(rename = "path")]
+    pub path: String,
+
+    /// a Tera expression, as an action's `if:`
+    #[serde(rename = "when")]
+    pub when: String,
+}
+
+impl OptionalPath {
+    /// Should the files matching the pattern be copied, given the vars?
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the condition is illegal, or uses a variable that isn't set
+    pub fn holds(&self, vars: &BTreeMap<String, String>) -> Result<bool> {
+        templates::eval_condition(&self.when, vars)
+            .map_err(|err| Error::config(format!("in optional path '{}': {}", self.path, err)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ProjectSetupActions {
     #[serde(rename = "actions")]
-    pub actions: Option<Vec<Action>>,
+    pub actions: Option<Vec<ProjectAction>>,
 
     #[serde(rename = "swaps")]
     pub swaps: Option<Vec<Swap>>,
+
+    #[serde(rename = "exclude")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+
+    #[serde(rename = "include")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+
+    #[serde(rename = "render")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render: Option<Vec<String>>,
+
+    #[serde(rename = "optional")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<Vec<OptionalPath>>,
+}
+
+impl From<&Project> for ProjectSetupActions {
+    fn from(project: &Project) -> Self {
+        Self {
+            actions: project.actions.clone(),
+            swaps: project.swaps.clone(),
+            exclude: project.exclude.clone(),
+            include: project.include.clone(),
+            render: project.render.clone(),
+            optional: project.optional.clone(),
+        }
+    }
+}
+
+impl ProjectSetupActions {
+    /// Combine the setup a template ships with and the one a user configured for it.
+    /// The template's actions run first, and a user swap replaces a template swap of the same key.
+    /// Patterns add up, the user's coming last so they can re-include what a template excludes.
+    pub fn merge(template: Option<Self>, user: Option<Self>) -> Option<Self> {
+        match (template, user) {
+            (Some(template), Some(user)) => {
+                let actions = match (template.actions, user.actions) {
+                    (Some(mut actions), Some(user_actions)) => {
+                        actions.extend(user_actions);
+                        Some(actions)
+                    }
+                    (actions, user_actions) => user_actions.or(actions),
+                };
+                let swaps = match (template.swaps, user.swaps) {
+                    (Some(mut swaps), Some(user_swaps)) => {
+                        swaps.retain(|s| !user_swaps.iter().any(|u| u.key == s.key));
+                        swaps.extend(user_swaps);
+                        Some(swaps)
+                    }
+                    (swaps, user_swaps) => user_swaps.or(swaps),
+                };
+                Some(Self {
+                    actions,
+                    swaps,
+                    exclude: concat(template.exclude, user.exclude),
+                    include: concat(template.include, user.include),
+                    render: concat(template.render, user.render),
+                    optional: concat(template.optional, user.optional),
+                })
+            }
+            (template, user) => user.or(template),
+        }
+    }
+}
+
+fn concat<T>(first: Option<Vec<T>>, second: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (first, second) {
+        (Some(mut first), Some(second)) => {
+            first.extend(second);
+            Some(first)
+        }
+        (first, second) => second.or(first),
+    }
+}
+
+/// Entries of each list that the other list also allows, given `covers(entry, other)`
+/// telling whether `entry` allows everything `other` does. A missing list allows all.
+fn intersect(
+    first: Option<Vec<String>>,
+    second: Option<Vec<String>>,
+    covers: impl Fn(&str, &str) -> bool,
+) -> Option<Vec<String>> {
+    match (first, second) {
+        (Some(first), Some(second)) => {
+            let mut both: Vec<String> = Vec::new();
+            let allowed = first
+                .iter()
+                .filter(|entry| second.iter().any(|other| covers(other, entry)))
+                .chain(
+                    second
+                        .iter()
+                        .filter(|entry| first.iter().any(|other| covers(other, entry))),
+                );
+            for entry in allowed {
+                if !both.iter().any(|e| e.eq_ignore_ascii_case(entry)) {
+                    both.push(entry.clone());
+                }
+            }
+            Some(both)
+        }
+        (first, second) => first.or(second),
+    }
+}
+
+/// Which hosts and vendors templates may come from. Deny rules win over allow rules,
+/// and when an allow list is set, anything not on it is blocked.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Policy {
+    #[serde(rename = "allow", default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow: Option<PolicyRules>,
+
+    #[serde(rename = "deny", default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deny: Option<PolicyRules>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PolicyRules {
+    /// domains, exact or with a leading `*.` for any subdomain
+    #[serde(rename = "domains", default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domains: Option<Vec<String>>,
+
+    /// vendor kinds: `github`, `gitlab`, `bitbucket`, `gist` or `local`
+    #[serde(rename = "vendors", default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendors: Option<Vec<String>>,
+}
+
+impl Policy {
+    /// Layer `other` on top of this policy, so that it can only get stricter: deny
+    /// lists add up, and where both set an allow list, only what both allow stays.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        let (allow, other_allow) = (self.allow.unwrap_or_default(), other.allow);
+        let (deny, other_deny) = (self.deny.unwrap_or_default(), other.deny);
+        let rules = |rules: PolicyRules| {
+            (rules.domains.is_some() || rules.vendors.is_some()).then_some(rules)
+        };
+        Self {
+            allow: rules(PolicyRules {
+                domains: intersect(
+                    allow.domains,
+                    other_allow.as_ref().and_then(|a| a.domains.clone()),
+                    pattern_covers,
+                ),
+                vendors: intersect(
+                    allow.vendors,
+                    other_allow.as_ref().and_then(|a| a.vendors.clone()),
+                    |kind, other| kind.eq_ignore_ascii_case(other),
+                ),
+            }),
+            deny: rules(PolicyRules {
+                domains: concat(
+                    deny.domains,
+                    other_deny.as_ref().and_then(|d| d.domains.clone()),
+                ),
+                vendors: concat(
+                    deny.vendors,
+                    other_deny.as_ref().and_then(|d| d.vendors.clone()),
+                ),
+            }),
+        }
+    }
+
+    /// Check a link that expands to `domain` with a `vendor` kind against the policy,
+    /// before anything is fetched. Domains of local templates aren't checked, they
+    /// have none.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::Blocked`] naming the rule that blocks the link
+    pub fn check(&self, link: &str, vendor: &str, domain: &str) -> Result<()> {
+        let blocked = |rule: String| {
+            Err(Error::Blocked {
+                link: link.to_string(),
+                rule,
+            })
+        };
+        let has_domain = !domain.is_empty();
+        let deny = self.deny.as_ref();
+        if let Some(pattern) = deny.and_then(|d| d.domains.as_ref()).and_then(|patterns| {
+            patterns
+                .iter()
+                .find(|p| has_domain && domain_matches(p, domain))
+        }) {
+            return blocked(format!("policy.deny.domains has '{pattern}'"));
+        }
+        if let Some(kind) = deny
+            .and_then(|d| d.vendors.as_ref())
+            .and_then(|kinds| kinds.iter().find(|k| k.eq_ignore_ascii_case(vendor)))
+        {
+            return blocked(format!("policy.deny.vendors has '{kind}'"));
+        }
+        let allow = self.allow.as_ref();
+        if let Some(patterns) = allow.and_then(|a| a.domains.as_ref()) {
+            if has_domain && !patterns.iter().any(|p| domain_matches(p, domain)) {
+                return blocked(format!("'{domain}' is not in policy.allow.domains"));
+            }
+        }
+        if let Some(kinds) = allow.and_then(|a| a.vendors.as_ref()) {
+            if !kinds.iter().any(|k| k.eq_ignore_ascii_case(vendor)) {
+                return blocked(format!("'{vendor}' is not in policy.allow.vendors"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lists = [
+            (
+                "allow domains",
+                self.allow.as_ref().and_then(|a| a.domains.as_ref()),
+            ),
+            (
+                "allow vendors",
+                self.allow.as_ref().and_then(|a| a.vendors.as_ref()),
+            ),
+            (
+                "deny domains",
+                self.deny.as_ref().and_then(|d| d.domains.as_ref()),
+            ),
+            (
+                "deny vendors",
+                self.deny.as_ref().and_then(|d| d.vendors.as_ref()),
+            ),
+        ];
+        let mut any = false;
+        for (name, list) in lists {
+            if let Some(list) = list {
+                writeln!(f, "  {}: {}", name, list.join(", "))?;
+                any = true;
+            }
+        }
+        if !any {
+            writeln!(f, "  any host and vendor is allowed")?;
+        }
+        Ok(())
+    }
+}
+
+/// `*.example.com` matches any subdomain of `example.com`, other patterns match exactly
+fn domain_matches(pattern: &str, domain: &str) -> bool {
+    let domain = domain.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    pattern.strip_prefix("*.").map_or_else(
+        || domain == pattern,
+        |parent| domain.ends_with(&format!(".{parent}")),
+    )
+}
+
+/// Whether domain `pattern` allows everything `other` (a domain or a pattern) does
+fn pattern_covers(pattern: &str, other: &str) -> bool {
+    other.strip_prefix("*.").map_or_else(
+        || domain_matches(pattern, other),
+        |parent| {
+            pattern.eq_ignore_ascii_case(other) || domain_matches(pattern, &format!("x.{parent}"))
+        },
+    )
+}
+
+/// Who may run commands on this machine through a template's own actions
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TrustConfig {
+    /// hosts whose templates may run their actions, once trusted. When set, actions
+    /// of templates from any other host never run.
+    #[serde(rename = "hosts")]
+    pub hosts: Option<Vec<String>>,
+}
+
+impl TrustConfig {
+    /// Layer `other` on top of this one, so that it can only get stricter: where both
+    /// list hosts, only the hosts both list stay.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            hosts: intersect(self.hosts, other.hosts, |host, other| {
+                host.eq_ignore_ascii_case(other)
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -265,6 +793,23 @@ pub struct CustomVendor {
 
     #[serde(rename = "base")]
     pub base: String,
+
+    #[serde(rename = "transport")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+
+    /// port the host serves ssh on, when it isn't the one in `base`'s https url
+    #[serde(rename = "ssh_port")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_port: Option<u16>,
+
+    /// user to connect with over ssh, `git` by default
+    #[serde(rename = "ssh_user")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_user: Option<String>,
 }
 
 #[cfg(test)]
@@ -321,6 +866,277 @@ projects:
         assert_debug_snapshot!(config_fully_loaded);
     }
 
+    #[test]
+    fn test_local_config_file_walks_up() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a/b/c");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(Config::local_config_file(&nested), None);
+
+        fs::write(root.path().join("a/backpack.yaml"), "projects: {}").unwrap();
+        assert_eq!(
+            Config::local_config_file(&nested),
+            Some(root.path().join("a/backpack.yaml"))
+        );
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, text).unwrap();
+            path
+        };
+        let global = write(
+            "global.yaml",
+            r###"
+projects:
+  rust:
+    shortlink: global/rust
+    actions:
+      - name: global action
+        run: echo global
+  node:
+    shortlink: global/node
+vendors:
+  default:
+    kind: github
+    base: github.com/global
+  custom:
+    gl:
+      kind: gitlab
+      base: gitlab.com/global
+"###,
+        );
+        let local = write(
+            "local.yaml",
+            r###"
+projects:
+  rust:
+    shortlink: local/rust
+vendors:
+  custom:
+    ghe:
+      kind: github
+      base: ghe.example.com
+"###,
+        );
+
+        let config = Config::from_path(&global)
+            .unwrap()
+            .merge(Config::from_path(&local).unwrap())
+            .unwrap();
+
+        let rust = config.project("rust").unwrap();
+        assert_eq!(rust.shortlink, "local/rust");
+        // replaced whole, actions don't leak from the lower layer
+        assert!(rust.actions.is_none());
+        assert_eq!(config.project("node").unwrap().shortlink, "global/node");
+        assert_eq!(config.project_files["rust"], local);
+        assert_eq!(config.project_files["node"], global);
+
+        let vendors = config.vendors.unwrap();
+        assert_eq!(vendors.vendors_default.unwrap().base, "github.com/global");
+        let custom = vendors.custom.unwrap();
+        assert!(custom.contains_key("gl"));
+        assert!(custom.contains_key("ghe"));
+    }
+
+    #[test]
+    fn test_merge_setup_actions() {
+        let setup = |text: &str| {
+            let local = LocalProjectConfig::from_text(text).unwrap();
+            Some(ProjectSetupActions::from(&local.project))
+        };
+        let template = setup(
+            r###"
+project:
+  shortlink: template
+  actions:
+    - name: template action
+      run: echo template
+  swaps:
+    - key: NAME
+      val: template
+    - key: AUTHOR
+      val: template
+  exclude:
+    - .github
+"###,
+        );
+        let user = setup(
+            r###"
+project:
+  shortlink: user
+  actions:
+    - name: user action
+      run: echo user
+  swaps:
+    - key: AUTHOR
+      val: user
+  include:
+    - .github/workflows/build.yml
+"###,
+        );
+
+        let merged = ProjectSetupActions::merge(template.clone(), user).unwrap();
+        assert_eq!(
+            merged
+                .actions
+                .unwrap()
+                .iter()
+                .map(|a| a.action.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["template action", "user action"]
+        );
+        assert_eq!(
+            merged
+                .swaps
+                .unwrap()
+                .iter()
+                .map(|s| (s.key.as_str(), s.val.as_deref().unwrap()))
+                .collect::<Vec<_>>(),
+            vec![("NAME", "template"), ("AUTHOR", "user")]
+        );
+        assert_eq!(merged.exclude, Some(vec![".github".to_string()]));
+        assert_eq!(
+            merged.include,
+            Some(vec![".github/workflows/build.yml".to_string()])
+        );
+
+        assert!(ProjectSetupActions::merge(None, None).is_none());
+        assert_eq!(
+            ProjectSetupActions::merge(template, None)
+                .unwrap()
+                .swaps
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_trust_merge() {
+        let hosts = |global: &str, local: &str| {
+            Config::from_text(global)
+                .unwrap()
+                .merge(Config::from_text(local).unwrap())
+                .unwrap()
+                .trust
+                .and_then(|trust| trust.hosts)
+        };
+        let global = "trust:\n  hosts: [github.com, gitlab.com]\n";
+
+        // a local trust config can narrow the hosts, never add one
+        assert_eq!(
+            hosts(global, "trust:\n  hosts: [GitHub.com, evil.com]\n"),
+            Some(vec!["github.com".to_string()])
+        );
+        assert_eq!(
+            hosts(global, "trust: {}\n"),
+            Some(vec!["github.com".to_string(), "gitlab.com".to_string()])
+        );
+        assert_eq!(
+            hosts("projects: {}\n", "trust:\n  hosts: [evil.com]\n"),
+            Some(vec!["evil.com".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        let global = Config::from_text(
+            r###"
+policy:
+  allow:
+    domains: [github.com, "*.corp.com"]
+  deny:
+    vendors: [gist]
+"###,
+        )
+        .unwrap();
+        let local = Config::from_text(
+            r###"
+policy:
+  allow:
+    domains: [github.com, "*.example.com"]
+    vendors: [github, gitlab]
+  deny:
+    domains: [git.corp.com]
+"###,
+        )
+        .unwrap();
+        let policy = global.merge(local).unwrap().policy.unwrap();
+
+        let rule = |vendor: &str, domain: &str| match policy.check("link", vendor, domain) {
+            Err(Error::Blocked { rule, .. }) => Some(rule),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(()) => None,
+        };
+        assert_eq!(rule("github", "github.com"), None);
+        assert_eq!(
+            rule("gitlab", "GitLab.Corp.com").as_deref(),
+            Some("'GitLab.Corp.com' is not in policy.allow.domains")
+        );
+        assert_eq!(
+            rule("gitlab", "git.corp.com").as_deref(),
+            Some("policy.deny.domains has 'git.corp.com'")
+        );
+        assert_eq!(
+            rule("gist", "gist.github.com").as_deref(),
+            Some("policy.deny.vendors has 'gist'")
+        );
+        assert_eq!(
+            rule("github", "evil.example.com").as_deref(),
+            Some("'evil.example.com' is not in policy.allow.domains")
+        );
+        assert_eq!(
+            rule("github", "corp.com").as_deref(),
+            Some("'corp.com' is not in policy.allow.domains")
+        );
+        assert_eq!(
+            rule("local", "").as_deref(),
+            Some("'local' is not in policy.allow.vendors")
+        );
+        assert_eq!(
+            policy.to_string(),
+            "  allow domains: github.com\n  allow vendors: github, gitlab\n  deny domains: git.corp.com\n  deny vendors: gist\n"
+        );
+
+        // a local allow list narrows a global pattern to what both allow
+        let narrow = |global: &[&str], local: &[&str]| {
+            let allow = |domains: &[&str]| Policy {
+                allow: Some(PolicyRules {
+                    domains: Some(domains.iter().map(ToString::to_string).collect()),
+                    vendors: None,
+                }),
+                deny: None,
+            };
+            allow(global)
+                .merge(allow(local))
+                .allow
+                .unwrap()
+                .domains
+                .unwrap()
+        };
+        assert_eq!(
+            narrow(
+                &["*.corp.com"],
+                &["git.corp.com", "*.eu.corp.com", "corp.com"]
+            ),
+            vec!["git.corp.com", "*.eu.corp.com"]
+        );
+        assert_eq!(
+            narrow(&["git.corp.com", "*.eu.corp.com"], &["*.corp.com"]),
+            vec!["git.corp.com", "*.eu.corp.com"]
+        );
+        assert!(narrow(&["github.com"], &["gitlab.com"]).is_empty());
+        assert_eq!(
+            Policy::default().to_string(),
+            "  any host and vendor is allowed\n"
+        );
+    }
+
     #[test]
     #[serial]
     fn test_with_no_configs() {
@@ -1,16 +1,20 @@
-use crate::config::ProjectSetupActions;
-use crate::data::{Location, Opts, Overwrite};
-use crate::templates::{CopyResult, Swapper};
-use crate::ui::Prompt;
-use anyhow::Result;
+use crate::config::{ProjectAction, ProjectSetupActions, PROJECT_CONFIG_FILE};
+use crate::data::{Location, OnConflict, Opts, Overwrite};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::merge::{self, BaseStore, MergeOutcome, BASE_FOLDER};
+use crate::templates::{CopyResult, SwapOp, Swapper};
+use crate::ui::{OverwriteChoice, Prompter};
+use crate::{Error, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use interactive_actions::{
     data::ActionResult,
     data::{Action, ActionHook},
     ActionRunner,
 };
 use serde::Serialize;
-use std::collections::BTreeMap;
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 use tracing::warn;
 use walkdir;
 
@@ -67,15 +71,150 @@ impl Coordinate {
     }
 }
 
+/// What would happen to a single file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum FilePlan {
+    Create,
+    Overwrite,
+    /// it exists, and the user would be asked whether to overwrite it
+    Ask,
+    /// it exists, and would be three-way merged
+    Merge,
+    /// it exists, and would be overwritten after keeping a `.bak` copy
+    Backup,
+    /// it exists, and generation would stop
+    Conflict,
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedFile {
+    pub dest: PathBuf,
+    pub plan: FilePlan,
+    /// content swap hits, or `None` if the file would be copied as-is
+    pub swaps: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedAction {
+    pub name: String,
+    pub hook: ActionHook,
+    pub run: Option<String>,
+}
+
+/// Everything a generation would do, see [`Deployer::plan`]
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct Plan {
+    pub files: Vec<PlannedFile>,
+    pub actions: Vec<PlannedAction>,
+    /// keys of swaps that would not replace anything
+    pub unmatched_swaps: Vec<String>,
+}
+
 pub struct Deployer<'a> {
     action_runner: &'a mut ActionRunner,
+    bases: Option<BaseStore>,
+    previous: Option<Manifest>,
+    kept: Vec<PathBuf>,
+    unmatched_swaps: Vec<String>,
+}
+
+/// gitignore-style patterns at a template's root, of files not to copy
+pub const IGNORE_FILE: &str = ".backpackignore";
+
+/// Never copied, unless explicitly included
+const DEFAULT_EXCLUDE: &[&str] = &[
+    ".git",
+    PROJECT_CONFIG_FILE,
+    MANIFEST_FILE,
+    BASE_FOLDER,
+    IGNORE_FILE,
+];
+
+/// Read the patterns of the first [`IGNORE_FILE`] found in `folders`
+///
+/// # Errors
+///
+/// This function will return an error if a found file cannot be read
+pub fn read_ignore_file(folders: &[&Path]) -> Result<Option<Vec<String>>> {
+    for folder in folders {
+        let file = folder.join(IGNORE_FILE);
+        if file.is_file() {
+            let text = std::fs::read_to_string(&file)?;
+            return Ok(Some(text.lines().map(str::to_string).collect()));
+        }
+    }
+    Ok(None)
 }
 
-const DONT_COPY: &[&str] = &[".git"];
+/// What not to copy out of `from`: the defaults, then the setup's `exclude` patterns,
+/// then its `include` patterns which take files back in, and last its `optional`
+/// paths whose condition doesn't hold over the vars
+fn ignores(
+    from: &Path,
+    project_setup: Option<&ProjectSetupActions>,
+    vars: &BTreeMap<String, String>,
+) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(from);
+    let (exclude, include, optional) = project_setup.map_or((None, None, None), |p| {
+        (p.exclude.as_ref(), p.include.as_ref(), p.optional.as_ref())
+    });
+    let mut dropped = vec![];
+    for optional in optional.into_iter().flatten() {
+        if !optional.holds(vars)? {
+            dropped.push(optional.path.clone());
+        }
+    }
+    let lines = DEFAULT_EXCLUDE
+        .iter()
+        .map(|p| (*p).to_string())
+        .chain(exclude.into_iter().flatten().cloned())
+        .chain(include.into_iter().flatten().map(|p| format!("!{p}")))
+        .chain(dropped);
+    for line in lines {
+        builder
+            .add_line(None, &line)
+            .map_err(|e| Error::config(format!("bad pattern '{line}': {e}")))?;
+    }
+    builder
+        .build()
+        .map_err(|e| Error::config(format!("bad patterns: {e}")))
+}
 
 impl<'a> Deployer<'a> {
     pub fn new(action_runner: &'a mut ActionRunner) -> Self {
-        Self { action_runner }
+        Self {
+            action_runner,
+            bases: None,
+            previous: None,
+            kept: vec![],
+            unmatched_swaps: vec![],
+        }
+    }
+
+    /// Update a folder generated before, as described by its manifest: only files
+    /// that are still as they were generated are overwritten.
+    #[must_use]
+    pub fn with_previous(mut self, previous: Manifest) -> Self {
+        self.previous = Some(previous);
+        self
+    }
+
+    /// Files an update left alone, because they were edited or removed since generated
+    pub fn kept(&self) -> &[PathBuf] {
+        &self.kept
+    }
+
+    /// Keys of swaps that did not replace anything in the last deploy
+    pub fn unmatched_swaps(&self) -> &[String] {
+        &self.unmatched_swaps
+    }
+
+    /// Keep what's generated in `bases`, to merge with next time
+    #[must_use]
+    pub fn with_bases(mut self, bases: BaseStore) -> Self {
+        self.bases = Some(bases);
+        self
     }
 
     #[tracing::instrument(skip_all, err)]
@@ -85,7 +224,7 @@ impl<'a> Deployer<'a> {
         project_setup: Option<ProjectSetupActions>,
         vars: &mut BTreeMap<String, String>,
         opts: &Opts,
-        prompt: &mut Prompt<'_>,
+        prompt: &mut dyn Prompter,
     ) -> Result<(Vec<CopyResult>, Option<Vec<ActionResult>>)> {
         // xxx: either way canonicalize paths.
         let actions_dest = if coord.is_file {
@@ -97,32 +236,61 @@ impl<'a> Deployer<'a> {
         let (actions, swaps) = project_setup
             .as_ref()
             .map_or((None, None), |p| (p.actions.as_ref(), p.swaps.as_ref()));
-
-        if let Some(actions) = actions {
-            self.action_runner.run(
-                actions,
-                Some(actions_dest),
-                vars,
-                ActionHook::Before,
-                None::<fn(&Action)>,
-            )?;
+        let actions = actions.map(|actions| unanswered(actions, vars));
+        let actions = actions.as_deref();
+        if opts.non_interactive || opts.headless {
+            ensure_answered(actions.unwrap_or_default(), vars)?;
         }
 
-        let swapper = Swapper::with_vars(swaps, vars)?;
+        let before_actions = if let Some(actions) = actions {
+            Some(self.run_actions(actions, Some(actions_dest), vars, &ActionHook::Before, None)?)
+        } else {
+            None
+        };
+
+        let swapper = Swapper::with_vars(swaps, vars)?.with_templates(
+            render_patterns(project_setup.as_ref()),
+            &coord.from,
+            &coord.to,
+        )?;
+        let ignore = ignores(&coord.from, project_setup.as_ref(), vars)?;
+
+        // make sure required swaps hit before writing anything
+        if swapper.swaps().iter().any(|s| s.required) {
+            let mut matched = BTreeSet::new();
+            for (from, to) in Self::sources(&coord.from, &coord.to, coord.is_file, &ignore)? {
+                matched.extend(swapper.matches(&from, &to)?);
+            }
+            let missing = swapper
+                .unmatched_in(&matched)
+                .into_iter()
+                .filter(|s| s.required)
+                .map(|s| format!("'{}'", s.key))
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                return Err(Error::config(format!(
+                    "required swap(s) did not match anything: {}",
+                    missing.join(", ")
+                )));
+            }
+        }
 
         let files = self.copy(
             &swapper,
+            &ignore,
             &coord.from,
             &coord.to,
             coord.is_file,
-            if opts.overwrite {
-                Overwrite::Always
-            } else {
-                Overwrite::Ask
-            },
+            overwrite_mode(opts),
             prompt,
         )?;
 
+        self.unmatched_swaps = swapper
+            .unmatched()
+            .into_iter()
+            .map(|s| s.key.clone())
+            .collect();
+
         if coord.remove_from {
             // xxx don't remove for now
             warn!(
@@ -132,90 +300,794 @@ impl<'a> Deployer<'a> {
         }
 
         let after_actions = if let Some(actions) = actions {
-            Some(self.action_runner.run(
+            Some(self.run_actions(
                 actions,
                 Some(actions_dest),
                 vars,
-                ActionHook::After,
-                Some(|action: &Action| prompt.say_action(action.name.as_str())),
+                &ActionHook::After,
+                Some(&|action: &Action| prompt.say_action(action.name.as_str())),
             )?)
         } else {
             None
         };
 
-        Ok((files, after_actions))
+        // report both hooks' outcomes, in the order they ran
+        let all_actions = match (before_actions, after_actions) {
+            (None, None) => None,
+            (before, after) => Some(
+                before
+                    .into_iter()
+                    .flatten()
+                    .chain(after.into_iter().flatten())
+                    .collect::<Vec<_>>(),
+            ),
+        };
+
+        Ok((files, all_actions))
+    }
+
+    /// Run the actions of a hook one by one, skipping those whose condition doesn't
+    /// hold over the vars as they are by their turn
+    fn run_actions(
+        &mut self,
+        actions: &[ProjectAction],
+        working_dir: Option<&Path>,
+        vars: &mut BTreeMap<String, String>,
+        hook: &ActionHook,
+        progress: Option<&dyn Fn(&Action)>,
+    ) -> Result<Vec<ActionResult>> {
+        let mut results = vec![];
+        for action in actions.iter().filter(|a| a.action.hook == *hook) {
+            if action.applies(vars)? {
+                results.extend(self.action_runner.run(
+                    std::slice::from_ref(&action.action),
+                    working_dir,
+                    vars,
+                    hook.clone(),
+                    progress,
+                )?);
+            }
+        }
+        Ok(results)
     }
 
+    /// Work out what [`Deployer::deploy`] would do, without writing files or running
+    /// scripts. Actions that only interact with the user still run before planning,
+    /// so that swaps depending on their answers can be computed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if interactions or reading the source fail
     #[tracing::instrument(skip_all, err)]
-    fn copy(
-        &self,
-        swapper: &Swapper,
+    pub fn plan(
+        &mut self,
+        coord: &Coordinate,
+        project_setup: Option<&ProjectSetupActions>,
+        vars: &mut BTreeMap<String, String>,
+        opts: &Opts,
+    ) -> Result<Plan> {
+        let (actions, swaps) =
+            project_setup.map_or((None, None), |p| (p.actions.as_ref(), p.swaps.as_ref()));
+
+        if let Some(actions) = actions {
+            let pending = unanswered(actions, vars);
+            if opts.non_interactive || opts.headless {
+                ensure_answered(&pending, vars)?;
+            }
+            let interactions = pending
+                .into_iter()
+                .filter(|a| a.action.run.is_none())
+                .collect::<Vec<_>>();
+            self.run_actions(&interactions, None, vars, &ActionHook::Before, None)?;
+        }
+
+        let swapper = Swapper::with_vars(swaps, vars)?.with_templates(
+            render_patterns(project_setup),
+            &coord.from,
+            &coord.to,
+        )?;
+        let overwrite = overwrite_mode(opts);
+        let ignore = ignores(&coord.from, project_setup, vars)?;
+        let sources = Self::sources(&coord.from, &coord.to, coord.is_file, &ignore)?;
+        ensure_contained(&swapper, &coord.from, &coord.to, coord.is_file, &sources)?;
+        // swaps count as matched only in files that would be written
+        let mut matched = BTreeSet::new();
+        let files = sources
+            .into_iter()
+            .map(|(from, to)| {
+                let planned = swapper.plan(&from, &to)?;
+                let plan = if planned.dest.exists() {
+                    match overwrite {
+                        Overwrite::Always => FilePlan::Overwrite,
+                        Overwrite::Ask => FilePlan::Ask,
+                        Overwrite::Merge => FilePlan::Merge,
+                        Overwrite::Backup => FilePlan::Backup,
+                        Overwrite::Fail => FilePlan::Conflict,
+                        _ => FilePlan::Skip,
+                    }
+                } else {
+                    FilePlan::Create
+                };
+                if !matches!(plan, FilePlan::Skip | FilePlan::Conflict) {
+                    matched.extend(swapper.matches(&from, &to)?);
+                }
+                Ok(PlannedFile {
+                    swaps: match planned.op {
+                        SwapOp::Rendered(count) => Some(count),
+                        SwapOp::Copied => None,
+                    },
+                    dest: planned.dest,
+                    plan,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // before hooks first, in the order they'd run. A condition that can't be told
+        // yet (it may need an answer only given after copying) could go either way.
+        let actions = actions
+            .into_iter()
+            .flatten()
+            .filter(|a| a.action.hook == ActionHook::Before)
+            .chain(
+                actions
+                    .into_iter()
+                    .flatten()
+                    .filter(|a| a.action.hook == ActionHook::After),
+            )
+            .filter(|a| a.applies(vars).unwrap_or(true))
+            .map(|a| &a.action)
+            .map(|a| PlannedAction {
+                name: a.name.clone(),
+                hook: a.hook.clone(),
+                run: a.run.clone(),
+            })
+            .collect();
+
+        let unmatched_swaps = swapper
+            .unmatched_in(&matched)
+            .into_iter()
+            .map(|s| s.key.clone())
+            .collect();
+        Ok(Plan {
+            files,
+            actions,
+            unmatched_swaps,
+        })
+    }
+
+    /// List the files to copy as `(from, to)` pairs, `to` before swapping
+    fn sources(
         source: &Path,
         dest: &Path,
         is_file: bool,
-        overwrite: Overwrite,
-        prompt: &mut Prompt<'_>,
-    ) -> Result<Vec<CopyResult>> {
+        ignore: &Gitignore,
+    ) -> Result<Vec<(PathBuf, PathBuf)>> {
         if is_file {
-            return Ok(vec![swapper.copy_to(source, dest)?]);
+            return Ok(vec![(source.to_path_buf(), dest.to_path_buf())]);
         }
 
-        let mut copied = vec![];
-        walkdir::WalkDir::new(source)
+        // an ignored folder can be skipped as a whole, unless something in it may be included
+        let can_prune = ignore.num_whitelists() == 0;
+        let mut files = vec![];
+        for entry in walkdir::WalkDir::new(source)
             .into_iter()
             .filter_entry(|entry| {
                 let path = entry.path();
-                !DONT_COPY.iter().any(|c| path.ends_with(c))
+                // whatever is included, these are never template content
+                entry.depth() == 0
+                    || !(path.ends_with(".git")
+                        || path.ends_with(BASE_FOLDER)
+                        || can_prune
+                            && entry.file_type().is_dir()
+                            && ignore.matched(path, true).is_ignore())
             })
-            .try_for_each(|entry| {
-                let entry = entry?;
-                let path = entry.path();
+        {
+            let entry = entry.map_err(anyhow::Error::from)?;
+            let path = entry.path();
+            if path.is_file() && !ignore.matched_path_or_any_parents(path, false).is_ignore() {
+                let to = dest.join(path.strip_prefix(source).map_err(anyhow::Error::from)?);
+                files.push((path.to_path_buf(), to));
+            }
+        }
+        Ok(files)
+    }
 
-                if path.is_file() {
-                    let to = dest.join(path.strip_prefix(source)?);
-                    let to_path = to.as_path();
-
-                    //
-                    // trading off some inefficiency for readability here.
-                    // the swapped path can be created only once, but instead it's created
-                    // many times below in `exists`, in prompt, and by each of the `copy`s
-                    // instead of creating a swapped path and *remembering* to pass throughout the workflow,
-                    // we hide its creation.
-                    //
-                    // in addition, each `copy` will check to see if the parent exists, and if not, create it.
-                    // we could create the parent just once here, but again - hiding it inside swapper is better
-                    // than remembering nuances in the prices of checking parent folder more times than needed.
-                    //
-                    if swapper.exists(to_path) {
-                        let should_copy = match overwrite {
-                            Overwrite::Always => true,
-                            Overwrite::Ask => prompt
-                                .confirm_overwrite(swapper.path(to_path).as_path())
-                                .unwrap_or(false),
-                            _ => false,
-                        };
-                        if should_copy {
-                            let to_swapped = swapper.copy_to(path, to_path)?;
-                            copied.push(to_swapped);
+    #[tracing::instrument(skip_all, err)]
+    #[allow(clippy::too_many_arguments)]
+    fn copy(
+        &mut self,
+        swapper: &Swapper,
+        ignore: &Gitignore,
+        source: &Path,
+        dest: &Path,
+        is_file: bool,
+        overwrite: Overwrite,
+        prompt: &mut dyn Prompter,
+    ) -> Result<Vec<CopyResult>> {
+        let sources = Self::sources(source, dest, is_file, ignore)?;
+        ensure_contained(swapper, source, dest, is_file, &sources)?;
+        // failing on a conflict leaves nothing half-generated
+        if matches!(overwrite, Overwrite::Fail) && self.previous.is_none() {
+            if let Some((_, to)) = sources.iter().find(|(_, to)| swapper.exists(to)) {
+                return Err(Error::Conflict(swapper.path(to)));
+            }
+        }
+
+        let mut copied = vec![];
+        // set once the user answers "overwrite all" or "skip all"
+        let mut for_all: Option<bool> = None;
+        for (path, to) in sources {
+            let to_path = to.as_path();
+
+            //
+            // trading off some inefficiency for readability here.
+            // the swapped path can be created only once, but instead it's created
+            // many times below in `exists`, in prompt, and by each of the `copy`s
+            // instead of creating a swapped path and *remembering* to pass throughout the workflow,
+            // we hide its creation.
+            //
+            // in addition, each `copy` will check to see if the parent exists, and if not, create it.
+            // we could create the parent just once here, but again - hiding it inside swapper is better
+            // than remembering nuances in the prices of checking parent folder more times than needed.
+            //
+            if let Some(previous) = self.previous.as_ref() {
+                let swapped = swapper.path(to_path);
+                if !swapped.exists() && !previous.generated(dest, &swapped)
+                    || swapped.exists() && previous.is_pristine(dest, &swapped)?
+                {
+                    copied.push(self.write(swapper, &path, to_path)?);
+                } else if swapped.exists() && matches!(overwrite, Overwrite::Merge) {
+                    copied.push(self.merge(swapper, &path, to_path)?);
+                } else {
+                    self.kept.push(swapped);
+                }
+                continue;
+            }
+            if swapper.exists(to_path) {
+                if matches!(overwrite, Overwrite::Merge) {
+                    copied.push(self.merge(swapper, &path, to_path)?);
+                    continue;
+                }
+                let should_copy = match (&overwrite, for_all) {
+                    (Overwrite::Always, _) => true,
+                    (Overwrite::Fail, _) => return Err(Error::Conflict(swapper.path(to_path))),
+                    (Overwrite::Backup, _) => {
+                        let existing = swapper.path(to_path);
+                        fs::copy(&existing, backup_path(&existing))?;
+                        true
+                    }
+                    (Overwrite::Ask, Some(all)) => all,
+                    (Overwrite::Ask, None) => {
+                        let new_content = swapper.content(&path)?;
+                        // only a "no" skips; a prompt that can't ask has to stop the run
+                        let choice = prompt.confirm_overwrite(
+                            swapper.path(to_path).as_path(),
+                            new_content.as_deref(),
+                        )?;
+                        match choice {
+                            OverwriteChoice::Overwrite => true,
+                            OverwriteChoice::Skip => false,
+                            OverwriteChoice::OverwriteAll => {
+                                for_all = Some(true);
+                                true
+                            }
+                            OverwriteChoice::SkipAll => {
+                                for_all = Some(false);
+                                false
+                            }
                         }
-                    } else {
-                        let to_swapped = swapper.copy_to(path, to_path)?;
-                        copied.push(to_swapped);
                     }
+                    _ => false,
+                };
+                if should_copy {
+                    copied.push(self.write(swapper, &path, to_path)?);
                 }
-
-                anyhow::Ok(())
-            })?;
+            } else {
+                copied.push(self.write(swapper, &path, to_path)?);
+            }
+        }
         Ok(copied)
     }
+
+    /// Copy a file over, and keep what was written as the base for a later merge
+    fn write(&self, swapper: &Swapper, source: &Path, dest: &Path) -> Result<CopyResult> {
+        let res = swapper.copy_to(source, dest)?;
+        if let Some(bases) = self.bases.as_ref() {
+            // binaries are never merged, they need no base
+            if let Some(content) = swapper.content(source)? {
+                bases.write(&res.dest, content.as_bytes())?;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Three-way merge a file into an existing one: the last generated content is the base,
+    /// the existing file is ours, and the template's content is theirs.
+    fn merge(&self, swapper: &Swapper, source: &Path, dest: &Path) -> Result<CopyResult> {
+        let mut res = swapper.plan(source, dest)?;
+        let local = String::from_utf8(std::fs::read(&res.dest)?);
+        let (Some(template), Ok(local)) = (swapper.content(source)?, local) else {
+            // can't merge binaries, keep what's there
+            res.merge = Some(MergeOutcome::Binary);
+            return Ok(res);
+        };
+
+        let base = match self.bases.as_ref() {
+            Some(bases) => bases
+                .read(&res.dest)?
+                .and_then(|base| String::from_utf8(base).ok()),
+            None => None,
+        };
+        let (merged, outcome) = merge::three_way(base.as_deref(), &local, &template);
+        std::fs::write(&res.dest, merged)?;
+        swapper.record_written(source, dest)?;
+        if let Some(bases) = self.bases.as_ref() {
+            bases.write(&res.dest, template.as_bytes())?;
+        }
+        res.merge = Some(outcome);
+        Ok(res)
+    }
+}
+
+fn render_patterns(project_setup: Option<&ProjectSetupActions>) -> &[String] {
+    project_setup
+        .and_then(|p| p.render.as_deref())
+        .unwrap_or_default()
+}
+
+/// Actions without the interactions whose answers are already known
+fn unanswered(actions: &[ProjectAction], vars: &BTreeMap<String, String>) -> Vec<ProjectAction> {
+    actions
+        .iter()
+        .cloned()
+        .map(|mut project_action| {
+            let action = &mut project_action.action;
+            let answered = action
+                .interaction
+                .as_ref()
+                .and_then(|i| i.out.as_ref())
+                .is_some_and(|out| vars.contains_key(out));
+            if answered {
+                action.interaction = None;
+            }
+            project_action
+        })
+        .collect()
+}
+
+/// Fail listing every variable (or, for interactions that keep no answer, every action)
+/// that would still be asked for. With nothing left to ask, the vars are final, so
+/// actions whose condition doesn't hold are left out.
+fn ensure_answered(actions: &[ProjectAction], vars: &BTreeMap<String, String>) -> Result<()> {
+    let missing = actions
+        .iter()
+        .filter(|a| a.applies(vars).unwrap_or(true))
+        .map(|a| &a.action)
+        .filter_map(|action| {
+            action.interaction.as_ref().map(|interaction| {
+                interaction
+                    .out
+                    .clone()
+                    .unwrap_or_else(|| format!("(action '{}')", action.name))
+            })
+        })
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MissingVars(missing))
+    }
+}
+
+/// `<file>.bak`, or when that's taken, the first free of `<file>.bak.1`, `<file>.bak.2`, ...
+fn backup_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".bak");
+    let first = PathBuf::from(&name);
+    if !first.exists() {
+        return first;
+    }
+    (1..)
+        .map(|n| {
+            let mut numbered = name.clone();
+            numbered.push(format!(".{n}"));
+            PathBuf::from(numbered)
+        })
+        .find(|p| !p.exists())
+        .expect("there's always a free name")
+}
+
+/// Make sure generating stays inside its folders, before anything is written: every
+/// file of the template has to be in `source` (and not link out of it), and every
+/// swapped path has to be in `dest`. For a single file, these are its parent folders.
+fn ensure_contained(
+    swapper: &Swapper,
+    source: &Path,
+    dest: &Path,
+    is_file: bool,
+    sources: &[(PathBuf, PathBuf)],
+) -> Result<()> {
+    let parent = |p: &Path| p.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
+    let (source_root, dest_root) = if is_file {
+        (parent(source), parent(dest))
+    } else {
+        (source.to_path_buf(), dest.to_path_buf())
+    };
+    let (source_root, dest_root) = (resolve(&source_root)?, resolve(&dest_root)?);
+    let entry = |p: &Path, root: &Path| {
+        let relative = p.strip_prefix(root).unwrap_or(p);
+        if relative.as_os_str().is_empty() {
+            p.file_name().map_or_else(|| p.to_path_buf(), PathBuf::from)
+        } else {
+            relative.to_path_buf()
+        }
+    };
+
+    for (from, to) in sources {
+        if !resolve(from).is_ok_and(|from| from.starts_with(&source_root)) {
+            return Err(Error::UnsafePath {
+                path: from.clone(),
+                cause: format!(
+                    "template entry '{}' links outside of the template",
+                    entry(from, source).display()
+                ),
+            });
+        }
+        let swapped = swapper.path(to);
+        if !resolve(&swapped).is_ok_and(|swapped| swapped.starts_with(&dest_root)) {
+            let keys = swapper.path_swaps(to);
+            let cause = if keys.is_empty() {
+                format!(
+                    "entry '{}' leads outside of '{}'",
+                    entry(to, dest).display(),
+                    dest_root.display()
+                )
+            } else {
+                format!(
+                    "swap {} turns entry '{}' into a path outside of '{}'",
+                    keys.iter()
+                        .map(|k| format!("'{k}'"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    entry(to, dest).display(),
+                    dest_root.display()
+                )
+            };
+            return Err(Error::UnsafePath {
+                path: swapped,
+                cause,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Resolve `path` the way writing to it would: `.` and `..` are applied in order, and
+/// any part that exists is canonicalized, so links are followed. Parts that don't exist
+/// yet are taken as they are.
+///
+/// # Errors
+///
+/// This function will return an error if an existing part cannot be canonicalized,
+/// e.g. a dangling link
+fn resolve(path: &Path) -> Result<PathBuf> {
+    let mut resolved = if path.is_relative() {
+        std::env::current_dir()?
+    } else {
+        PathBuf::new()
+    };
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => {
+                resolved.push(component);
+                if resolved.symlink_metadata().is_ok() {
+                    resolved = resolved.canonicalize()?;
+                }
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+const fn overwrite_mode(opts: &Opts) -> Overwrite {
+    if opts.merge {
+        Overwrite::Merge
+    } else if let Some(on_conflict) = opts.on_conflict {
+        match on_conflict {
+            OnConflict::Overwrite => Overwrite::Always,
+            OnConflict::Skip => Overwrite::Never,
+            OnConflict::Fail => Overwrite::Fail,
+            OnConflict::Backup => Overwrite::Backup,
+        }
+    } else if opts.overwrite {
+        Overwrite::Always
+    } else {
+        Overwrite::Ask
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::OptionalPath;
+    use crate::data::CopyMode;
     use insta::assert_yaml_snapshot;
+    use std::fs;
     use url::Url;
 
+    /// Answers overwrite questions from a script, and counts them. Past the script,
+    /// the user cancels.
+    struct ScriptedPrompt {
+        answers: Vec<OverwriteChoice>,
+        asked: usize,
+    }
+    impl Prompter for ScriptedPrompt {
+        fn fill_missing(
+            &mut self,
+            _shortlink: Option<&str>,
+            _dest: Option<&str>,
+            _mode: &CopyMode,
+        ) -> anyhow::Result<(String, Option<String>, bool)> {
+            anyhow::bail!("should not ask")
+        }
+        fn are_you_sure(&mut self, _text: &str) -> anyhow::Result<bool> {
+            anyhow::bail!("should not ask")
+        }
+        fn confirm_overwrite(
+            &mut self,
+            _file: &Path,
+            new_content: Option<&str>,
+        ) -> anyhow::Result<OverwriteChoice> {
+            assert_eq!(new_content, Some("new"));
+            self.asked += 1;
+            if self.answers.is_empty() {
+                return Err(Error::Cancelled.into());
+            }
+            Ok(self.answers.remove(0))
+        }
+        fn say_resolving(&self) {}
+        fn say_fetching(&self) {}
+        fn say_unpacking(&self) {}
+        fn say_action(&self, _name: &str) {}
+        fn say_done(
+            &self,
+            _res: &[CopyResult],
+            _maybe_actions: Option<&Vec<ActionResult>>,
+            _unmatched_swaps: &[String],
+        ) {
+        }
+        fn say_plan(&self, _plan: &Plan) {}
+    }
+
+    fn copy_over_existing(answers: Vec<OverwriteChoice>) -> Result<(usize, usize)> {
+        let source = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        for name in ["one.txt", "two.txt", "three.txt"] {
+            fs::write(source.path().join(name), "new").unwrap();
+            fs::write(dest.path().join(name), "old").unwrap();
+        }
+
+        let mut prompt = ScriptedPrompt { answers, asked: 0 };
+        let mut action_runner = ActionRunner::default();
+        let copied = Deployer::new(&mut action_runner).copy(
+            &Swapper::with_vars(None, &BTreeMap::new()).unwrap(),
+            &Gitignore::empty(),
+            source.path(),
+            dest.path(),
+            false,
+            Overwrite::Ask,
+            &mut prompt,
+        )?;
+        Ok((prompt.asked, copied.len()))
+    }
+
+    fn sources_with(exclude: &[&str], include: &[&str]) -> Vec<String> {
+        let patterns = |ps: &[&str]| Some(ps.iter().map(|p| (*p).to_string()).collect());
+        let setup = ProjectSetupActions {
+            exclude: patterns(exclude),
+            include: patterns(include),
+            ..ProjectSetupActions::default()
+        };
+        sources_for(&setup, &BTreeMap::new()).unwrap()
+    }
+
+    fn sources_for(
+        setup: &ProjectSetupActions,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<Vec<String>> {
+        let source = tempfile::tempdir().unwrap();
+        for file in [
+            ".git/config",
+            ".github/workflows/build.yml",
+            ".github/workflows/release.yml",
+            "node_modules/dep/index.js",
+            ".backpack-project.yaml",
+            ".backpack.base/src/main.rs",
+            "LICENSE",
+            "src/main.rs",
+        ] {
+            let path = source.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let ignore = ignores(source.path(), Some(setup), vars)?;
+        let mut files = Deployer::sources(source.path(), Path::new("out"), false, &ignore)?
+            .into_iter()
+            .map(|(_, to)| to.display().to_string().replace('\\', "/"))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
+    #[test]
+    fn test_sources_ignore() {
+        assert_eq!(
+            sources_with(&[], &[]),
+            vec![
+                "out/.github/workflows/build.yml",
+                "out/.github/workflows/release.yml",
+                "out/LICENSE",
+                "out/node_modules/dep/index.js",
+                "out/src/main.rs",
+            ]
+        );
+        assert_eq!(
+            sources_with(&["node_modules/", ".github", "LICENSE"], &[]),
+            vec!["out/src/main.rs"]
+        );
+        // included files come back, even from inside an excluded folder
+        assert_eq!(
+            sources_with(
+                &["node_modules/", ".github", "LICENSE"],
+                &["LICENSE", ".github/workflows/build.yml", ".backpack.base/"]
+            ),
+            vec![
+                "out/.github/workflows/build.yml",
+                "out/LICENSE",
+                "out/src/main.rs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sources_optional() {
+        let setup = ProjectSetupActions {
+            optional: Some(vec![
+                OptionalPath {
+                    path: ".github/".to_string(),
+                    when: "{{ ci }}".to_string(),
+                },
+                OptionalPath {
+                    path: "node_modules/".to_string(),
+                    when: "js == 'yes'".to_string(),
+                },
+            ]),
+            ..ProjectSetupActions::default()
+        };
+        let vars = |ci: &str, js: &str| {
+            BTreeMap::from([
+                ("ci".to_string(), ci.to_string()),
+                ("js".to_string(), js.to_string()),
+            ])
+        };
+        assert_eq!(
+            sources_for(&setup, &vars("false", "yes")).unwrap(),
+            vec![
+                "out/LICENSE",
+                "out/node_modules/dep/index.js",
+                "out/src/main.rs"
+            ]
+        );
+        assert_eq!(
+            sources_for(&setup, &vars("true", "no")).unwrap(),
+            vec![
+                "out/.github/workflows/build.yml",
+                "out/.github/workflows/release.yml",
+                "out/LICENSE",
+                "out/src/main.rs",
+            ]
+        );
+
+        let err = sources_for(&setup, &BTreeMap::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "configuration error: in optional path '.github/': condition '{{ ci }}': Variable `ci` not found in context"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_for_all() {
+        assert_eq!(
+            copy_over_existing(vec![OverwriteChoice::Skip, OverwriteChoice::OverwriteAll]).unwrap(),
+            (2, 2)
+        );
+        assert_eq!(
+            copy_over_existing(vec![OverwriteChoice::Overwrite, OverwriteChoice::SkipAll]).unwrap(),
+            (2, 1)
+        );
+        assert_eq!(
+            copy_over_existing(vec![
+                OverwriteChoice::Skip,
+                OverwriteChoice::Overwrite,
+                OverwriteChoice::Skip
+            ])
+            .unwrap(),
+            (3, 1)
+        );
+        // cancelling stops the run, it doesn't skip the file
+        assert!(matches!(
+            copy_over_existing(vec![OverwriteChoice::Skip]),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_overwrite_policies() {
+        let source = tempfile::tempdir().unwrap();
+        fs::write(source.path().join("one.txt"), "new").unwrap();
+        fs::write(source.path().join("two.txt"), "two").unwrap();
+        let copy_into = |dest: &Path, overwrite: Overwrite, is_file: bool| {
+            let (from, to) = if is_file {
+                (source.path().join("one.txt"), dest.join("one.txt"))
+            } else {
+                (source.path().to_path_buf(), dest.to_path_buf())
+            };
+            let mut prompt = ScriptedPrompt {
+                answers: vec![],
+                asked: 0,
+            };
+            let mut action_runner = ActionRunner::default();
+            let copied = Deployer::new(&mut action_runner)
+                .copy(
+                    &Swapper::with_vars(None, &BTreeMap::new()).unwrap(),
+                    &Gitignore::empty(),
+                    &from,
+                    &to,
+                    is_file,
+                    overwrite,
+                    &mut prompt,
+                )
+                .map(|copied| copied.len());
+            assert_eq!(prompt.asked, 0);
+            copied
+        };
+        let copy_with = |overwrite: Overwrite, is_file: bool| {
+            let dest = tempfile::tempdir().unwrap();
+            fs::write(dest.path().join("one.txt"), "old").unwrap();
+            (copy_into(dest.path(), overwrite, is_file), dest)
+        };
+        let read = |dest: &tempfile::TempDir, name: &str| {
+            fs::read_to_string(dest.path().join(name)).unwrap()
+        };
+
+        for is_file in [false, true] {
+            let (copied, dest) = copy_with(Overwrite::Never, is_file);
+            assert_eq!(copied.unwrap(), usize::from(!is_file));
+            assert_eq!(read(&dest, "one.txt"), "old");
+
+            let (copied, dest) = copy_with(Overwrite::Backup, is_file);
+            assert_eq!(copied.unwrap(), if is_file { 1 } else { 2 });
+            assert_eq!(read(&dest, "one.txt"), "new");
+            assert_eq!(read(&dest, "one.txt.bak"), "old");
+
+            // a second backup doesn't replace the first one
+            fs::write(dest.path().join("one.txt"), "edited").unwrap();
+            copy_into(dest.path(), Overwrite::Backup, is_file).unwrap();
+            assert_eq!(read(&dest, "one.txt.bak"), "old");
+            assert_eq!(read(&dest, "one.txt.bak.1"), "edited");
+
+            // nothing is written when anything conflicts
+            let (copied, dest) = copy_with(Overwrite::Fail, is_file);
+            assert!(matches!(copied, Err(Error::Conflict(file)) if file.ends_with("one.txt")));
+            assert_eq!(read(&dest, "one.txt"), "old");
+            assert!(!dest.path().join("two.txt").exists());
+        }
+    }
+
     #[test]
     fn test_coord_new() {
         let norm_paths =
@@ -279,4 +1151,111 @@ mod tests {
             false
         ).unwrap(),{ ".from" => norm_paths(), ".to" => norm_paths() });
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unsafe_paths() {
+        use crate::templates::Swap;
+        use std::os::unix::fs::symlink;
+
+        let copy = |source: &Path, dest: &Path, swaps: Option<&Vec<Swap>>| {
+            let mut action_runner = ActionRunner::default();
+            Deployer::new(&mut action_runner).copy(
+                &Swapper::with_vars(swaps, &BTreeMap::new()).unwrap(),
+                &Gitignore::empty(),
+                source,
+                dest,
+                false,
+                Overwrite::Always,
+                &mut ScriptedPrompt {
+                    answers: vec![],
+                    asked: 0,
+                },
+            )
+        };
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret"), "secret").unwrap();
+
+        // a swap that climbs out of the destination
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir_all(source.path().join("NAME")).unwrap();
+        fs::write(source.path().join("NAME/one.txt"), "one").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let swaps = vec![Swap {
+            key: "NAME".to_string(),
+            val: Some("../../escaped".to_string()),
+            ..Swap::default()
+        }];
+        match copy(source.path(), &dest.path().join("out"), Some(&swaps)) {
+            Err(Error::UnsafePath { path, cause }) => {
+                assert!(path.ends_with("escaped/one.txt"));
+                assert!(cause
+                    .starts_with("swap 'NAME' turns entry 'NAME/one.txt' into a path outside of"));
+            }
+            res => panic!("expected an unsafe path, got {res:?}"),
+        }
+        assert!(!dest.path().join("out").exists());
+        assert!(!dest.path().join("../escaped").exists());
+
+        // a template entry that links out of the template
+        let source = tempfile::tempdir().unwrap();
+        fs::write(source.path().join("one.txt"), "one").unwrap();
+        symlink(outside.path().join("secret"), source.path().join("leak")).unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        match copy(source.path(), dest.path(), None) {
+            Err(Error::UnsafePath { path, cause }) => {
+                assert!(path.ends_with("leak"));
+                assert_eq!(cause, "template entry 'leak' links outside of the template");
+            }
+            res => panic!("expected an unsafe path, got {res:?}"),
+        }
+        assert!(!dest.path().join("one.txt").exists());
+
+        // a link in the destination that leads out of it
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir_all(source.path().join("config")).unwrap();
+        fs::write(source.path().join("config/secret"), "overwritten").unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        symlink(outside.path(), dest.path().join("config")).unwrap();
+        match copy(source.path(), dest.path(), None) {
+            Err(Error::UnsafePath { cause, .. }) => {
+                assert!(cause.starts_with("entry 'config/secret' leads outside of"));
+            }
+            res => panic!("expected an unsafe path, got {res:?}"),
+        }
+        assert_eq!(
+            fs::read_to_string(outside.path().join("secret")).unwrap(),
+            "secret"
+        );
+
+        // links and climbing back that stay inside are fine
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir_all(source.path().join("NAME")).unwrap();
+        fs::write(source.path().join("NAME/one.txt"), "one").unwrap();
+        symlink(
+            source.path().join("NAME/one.txt"),
+            source.path().join("two.txt"),
+        )
+        .unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let swaps = vec![Swap {
+            key: "NAME".to_string(),
+            val: Some("a/../b".to_string()),
+            ..Swap::default()
+        }];
+        assert_eq!(
+            copy(source.path(), dest.path(), Some(&swaps))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            fs::read_to_string(dest.path().join("b/one.txt")).unwrap(),
+            "one"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.path().join("two.txt")).unwrap(),
+            "one"
+        );
+    }
 }
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+/// The classes of failures library users may want to tell apart.
+/// Anything that doesn't fit a class ends up in [`Error::Other`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// the repository doesn't exist, or isn't visible to us
+    #[error("repository not found: {0}")]
+    RepoNotFound(String),
+
+    /// the remote refused our credentials (or we had none)
+    #[error("authentication failed: {0}")]
+    AuthFailed(String),
+
+    /// the branch or tag asked for doesn't exist on the remote
+    #[error("no such ref found: {0}")]
+    NoSuchRef(String),
+
+    /// the user cancelled a prompt
+    #[error("cancelled by user")]
+    Cancelled,
+
+    /// running non-interactively, but some questions have no answer
+    #[error("missing variable(s), set them with --var or --vars-file: {}", .0.join(", "))]
+    MissingVars(Vec<String>),
+
+    /// running headless, but something needed to ask the user
+    #[error("cannot ask in headless mode: {0}")]
+    Headless(String),
+
+    /// a file to generate already exists, and conflicts are set to fail
+    #[error("'{}' already exists", .0.display())]
+    Conflict(PathBuf),
+
+    /// the configured policy doesn't allow generating from where a link points to
+    #[error("'{link}' is blocked by policy: {rule}")]
+    Blocked { link: String, rule: String },
+
+    /// a template would read or write outside of the folders it's generated from and to
+    #[error("unsafe path '{}': {cause}", path.display())]
+    UnsafePath { path: PathBuf, cause: String },
+
+    /// a configuration file couldn't be read or parsed
+    #[error("configuration error{}: {message}", path.as_ref().map(|p| format!(" in '{}'", p.display())).unwrap_or_default())]
+    Config {
+        path: Option<PathBuf>,
+        message: String,
+    },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// A configuration error that isn't tied to a file
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config {
+            path: None,
+            message: message.into(),
+        }
+    }
+
+    /// Point a configuration error at the file it came from
+    #[must_use]
+    pub fn in_file(self, file: &Path) -> Self {
+        match self {
+            Self::Config { message, .. } => Self::Config {
+                path: Some(file.to_path_buf()),
+                message,
+            },
+            err => err,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    // an `Error` may have been carried through `anyhow` (e.g. with added context),
+    // keep its class instead of burying it in `Other`.
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Self>() {
+            Ok(err) => err,
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::config(err.to_string())
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_keeps_class_through_anyhow() {
+        let res: anyhow::Result<()> = Err(Error::NoSuchRef("v1".to_string())).context("resolving");
+        assert!(matches!(
+            Error::from(res.unwrap_err()),
+            Error::NoSuchRef(r) if r == "v1"
+        ));
+
+        let other = Error::from(anyhow::anyhow!("something else"));
+        assert!(matches!(other, Error::Other(_)));
+        assert_eq!(other.to_string(), "something else");
+    }
+
+    #[test]
+    fn test_config_message() {
+        assert_eq!(
+            Error::Config {
+                path: Some(PathBuf::from("backpack.yaml")),
+                message: "bad".to_string()
+            }
+            .to_string(),
+            "configuration error in 'backpack.yaml': bad"
+        );
+        assert_eq!(Error::config("bad").to_string(), "configuration error: bad");
+    }
+
+    #[test]
+    fn test_missing_vars_message() {
+        assert_eq!(
+            Error::MissingVars(vec!["name".to_string(), "license".to_string()]).to_string(),
+            "missing variable(s), set them with --var or --vars-file: name, license"
+        );
+    }
+}
@@ -1,10 +1,10 @@
-use anyhow::Result as AnyResult;
 use cached_path::{Cache, Options};
 use std::path::{Path, PathBuf};
 
 use crate::{
     data::{Archive, ArchiveRoot, Assets, Location},
     git::GitProvider,
+    Error, Result,
 };
 
 pub struct Fetcher<'a> {
@@ -23,20 +23,22 @@ impl<'a> Fetcher<'a> {
         location: &Location,
         assets: &Assets,
         no_cache: bool,
-    ) -> AnyResult<(PathBuf, bool)> {
+    ) -> Result<(PathBuf, bool)> {
         if location.is_git {
             self.fetch_git(location)
         } else {
-            let archive = assets
-                .archive
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("no archive found"))?;
-            self.fetch_archive(archive, no_cache)
+            let archive = assets.archive.as_ref().ok_or_else(|| {
+                Error::RepoNotFound(format!(
+                    "no archive found for {}",
+                    location.redact(&location.url)
+                ))
+            })?;
+            self.fetch_archive(location, archive, no_cache)
         }
     }
 
     #[tracing::instrument(skip_all, err)]
-    fn fetch_git(&self, location: &Location) -> AnyResult<(PathBuf, bool)> {
+    fn fetch_git(&self, location: &Location) -> Result<(PathBuf, bool)> {
         let out = tempfile::tempdir()?.path().to_str().unwrap().to_string();
 
         self.git.shallow_clone(location, &out)?;
@@ -44,7 +46,14 @@ impl<'a> Fetcher<'a> {
     }
 
     #[tracing::instrument(skip_all, err)]
-    fn fetch_archive(&self, archive: &Archive, no_cache: bool) -> AnyResult<(PathBuf, bool)> {
+    fn fetch_archive(
+        &self,
+        location: &Location,
+        archive: &Archive,
+        no_cache: bool,
+    ) -> Result<(PathBuf, bool)> {
+        // what's shown in errors, without credentials
+        let shown = location.redact(&archive.url);
         let cache = Cache::builder()
             .dir(self.cache_path.to_path_buf())
             .progress_bar(None)
@@ -53,8 +62,16 @@ impl<'a> Fetcher<'a> {
             .build()
             .unwrap();
 
-        let extracted =
-            cache.cached_path_with_options(&archive.url, &Options::default().extract())?;
+        let extracted = cache
+            .cached_path_with_options(&archive.url, &Options::default().extract())
+            .map_err(|e| match e {
+                cached_path::Error::HttpStatusError(404) => Error::RepoNotFound(shown.clone()),
+                cached_path::Error::HttpStatusError(401 | 403) => Error::AuthFailed(shown.clone()),
+                e => Error::Other(
+                    anyhow::anyhow!(location.redact(&e.to_string()))
+                        .context(format!("cannot download archive '{shown}'")),
+                ),
+            })?;
         let dir = match archive.root {
             ArchiveRoot::FirstFolder => std::fs::read_dir(&extracted)
                 .unwrap()
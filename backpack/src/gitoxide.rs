@@ -0,0 +1,245 @@
+//! A [`GitProvider`] built on gitoxide, for environments without a `git` binary.
+//!
+//! Remote locations go through gitoxide's own transports. Local (`file://`) locations
+//! are read straight from the repository's object database, because gitoxide's file
+//! transport would otherwise spawn `git-upload-pack`.
+use crate::data::Location;
+use crate::git::{classify_stderr, pick_ref, GitProvider, RemoteInfo};
+use crate::{Error, Result};
+use anyhow::{anyhow, Context};
+use gix::bstr::ByteSlice;
+use gix::remote::Direction;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+#[derive(Default)]
+pub struct GitOxide {}
+
+impl GitOxide {
+    fn local_path(location: &Location) -> Option<&str> {
+        if location.url.starts_with("file://") {
+            Some(location.path.as_str())
+        } else {
+            None
+        }
+    }
+
+    /// gitoxide errors don't carry a class, but their messages include what the
+    /// remote said, which is all `git` gives us too. Credentials in `remote` are
+    /// left out of the error.
+    fn classify(location: &Location, remote: &str, err: anyhow::Error) -> Error {
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => {
+                let message = location.redact(&format!("{err:#}"));
+                classify_stderr(
+                    &location.redact(remote),
+                    location.gref.as_deref(),
+                    &message,
+                    anyhow!(message.clone()),
+                )
+            }
+        }
+    }
+
+    /// List refs in the shape `git ls-remote` prints them: `HEAD` first, then every
+    /// ref, with annotated tags followed by their peeled `^{}` entry.
+    fn ls_local(path: &Path) -> anyhow::Result<Vec<RemoteInfo>> {
+        let repo = gix::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let mut refs = vec![RemoteInfo {
+            revision: repo.head_id()?.to_string(),
+            ref_: "HEAD".to_string(),
+        }];
+
+        let platform = repo.references()?;
+        for reference in platform.all()? {
+            let mut reference = reference.map_err(|e| anyhow!(e))?;
+            let name = reference.name().as_bstr().to_string();
+            let Some(target) = reference.target().try_id().map(ToOwned::to_owned) else {
+                continue;
+            };
+            let peeled = reference.peel_to_id()?.detach();
+            refs.push(RemoteInfo {
+                revision: target.to_string(),
+                ref_: name.clone(),
+            });
+            if peeled != target {
+                refs.push(RemoteInfo {
+                    revision: peeled.to_string(),
+                    ref_: format!("{name}^{{}}"),
+                });
+            }
+        }
+        Ok(refs)
+    }
+
+    fn ls_remote_url(url: &str) -> anyhow::Result<Vec<RemoteInfo>> {
+        // gitoxide needs a repository to hang a remote on, a throwaway bare one will do
+        let scratch = tempfile::tempdir()?;
+        let repo = gix::init_bare(scratch.path())?;
+        let remote = repo
+            .remote_at(url)?
+            .with_refspecs(Some("+refs/*:refs/*"), Direction::Fetch)?;
+        let (ref_map, _) = remote
+            .connect(Direction::Fetch)
+            .with_context(|| format!("cannot connect to '{url}'"))?
+            .ref_map(
+                gix::progress::Discard,
+                gix::remote::ref_map::Options {
+                    prefix_from_spec_as_filter_on_remote: false,
+                    ..Default::default()
+                },
+            )
+            .with_context(|| format!("cannot list refs of '{url}'"))?;
+
+        let mut refs = vec![];
+        for r in &ref_map.remote_refs {
+            match r {
+                gix::protocol::handshake::Ref::Direct {
+                    full_ref_name,
+                    object,
+                }
+                | gix::protocol::handshake::Ref::Symbolic {
+                    full_ref_name,
+                    object,
+                    tag: None,
+                    ..
+                } => refs.push(RemoteInfo {
+                    revision: object.to_string(),
+                    ref_: full_ref_name.to_string(),
+                }),
+                gix::protocol::handshake::Ref::Peeled {
+                    full_ref_name,
+                    tag,
+                    object,
+                }
+                | gix::protocol::handshake::Ref::Symbolic {
+                    full_ref_name,
+                    tag: Some(tag),
+                    object,
+                    ..
+                } => {
+                    refs.push(RemoteInfo {
+                        revision: tag.to_string(),
+                        ref_: full_ref_name.to_string(),
+                    });
+                    refs.push(RemoteInfo {
+                        revision: object.to_string(),
+                        ref_: format!("{full_ref_name}^{{}}"),
+                    });
+                }
+                gix::protocol::handshake::Ref::Unborn { .. } => {}
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Shallow clone a remote into `out`, checking out `branch` if given.
+    fn clone_url(giturl: &str, branch: Option<&str>, out: &str) -> anyhow::Result<()> {
+        let mut prepare = gix::prepare_clone(giturl, out)
+            .with_context(|| format!("cannot clone: {giturl}"))?
+            .with_shallow(gix::remote::fetch::Shallow::DepthAtRemote(
+                1.try_into().expect("non-zero"),
+            ));
+        if let Some(branch) = branch {
+            prepare = prepare.with_ref_name(Some(branch))?;
+        }
+        let (mut checkout, _) = prepare
+            .fetch_then_checkout(gix::progress::Discard, &AtomicBool::new(false))
+            .with_context(|| format!("cannot clone: {giturl}"))?;
+        checkout
+            .main_worktree(gix::progress::Discard, &AtomicBool::new(false))
+            .with_context(|| format!("cannot check out: {giturl}"))?;
+        Ok(())
+    }
+
+    /// Write the tree `gref` (or `HEAD`) points to in a local repository into `out`.
+    fn checkout_local(path: &Path, gref: Option<&str>, out: &str) -> anyhow::Result<()> {
+        let repo = gix::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let id = match gref {
+            Some(gref) => {
+                let refs = Self::ls_local(path)?;
+                let found = refs
+                    .iter()
+                    .find(|r| r.ref_.ends_with(gref))
+                    .ok_or_else(|| Error::NoSuchRef(gref.to_string()))?;
+                repo.find_reference(found.ref_.as_str())?
+                    .peel_to_id()?
+                    .detach()
+            }
+            None => repo.head_id()?.detach(),
+        };
+        let tree = repo.find_object(id)?.peel_to_tree()?.id;
+
+        let mut index = repo.index_from_tree(&tree)?;
+        let mut opts =
+            repo.checkout_options(gix::worktree::stack::state::attributes::Source::IdMapping)?;
+        opts.destination_is_initially_empty = true;
+        std::fs::create_dir_all(out)?;
+        gix::worktree::state::checkout(
+            &mut index,
+            out,
+            repo.objects.clone().into_arc()?,
+            &gix::progress::Discard,
+            &gix::progress::Discard,
+            &AtomicBool::new(false),
+            opts,
+        )?;
+        Ok(())
+    }
+}
+
+impl GitProvider for GitOxide {
+    #[tracing::instrument(name = "gix_get_ref", skip_all, err)]
+    fn get_ref_or_default(&self, location: &Location) -> Result<RemoteInfo> {
+        pick_ref(location, &self.ls_remote(location)?)
+    }
+
+    #[tracing::instrument(name = "gix_ls_remote", skip_all, err)]
+    fn ls_remote(&self, location: &Location) -> Result<Vec<RemoteInfo>> {
+        match Self::local_path(location) {
+            Some(path) => {
+                Self::ls_local(Path::new(path)).map_err(|e| Self::classify(location, path, e))
+            }
+            None => {
+                let url = location.remote_url();
+                Self::ls_remote_url(&url).map_err(|e| Self::classify(location, &url, e))
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "gix_clone", skip_all, err)]
+    fn shallow_clone(&self, location: &Location, out: &str) -> Result<()> {
+        if let Some(path) = Self::local_path(location) {
+            return Self::checkout_local(Path::new(path), location.gref.as_deref(), out)
+                .map_err(|e| Self::classify(location, path, e));
+        }
+
+        let giturl = location.clone_url();
+        Self::clone_url(&giturl, location.gref.as_deref(), out)
+            .map_err(|e| Self::classify(location, &giturl, e))
+    }
+
+    fn get_local_url(&self) -> Result<String> {
+        let repo = gix::discover(".").context("cannot find a local repo")?;
+        let remote = repo
+            .find_remote("origin")
+            .context("cannot find local url")?;
+        match remote.url(Direction::Fetch) {
+            Some(url) => Ok(url.to_bstring().to_str_lossy().to_string()),
+            None => Err(anyhow!("no repo remote URL found").into()),
+        }
+    }
+
+    fn config_value(&self, key: &str) -> Option<String> {
+        // the repository we're in sees its own configuration on top of the user's
+        let value = match gix::discover(".") {
+            Ok(repo) => repo.config_snapshot().string(key).map(|v| v.to_string()),
+            Err(_) => gix::config::File::from_globals()
+                .ok()?
+                .string(key)
+                .map(|v| v.to_string()),
+        };
+        value.filter(|v| !v.is_empty())
+    }
+}
@@ -9,10 +9,19 @@
 pub mod config;
 pub mod content;
 pub mod data;
+pub mod error;
 pub mod fetch;
 pub mod git;
+#[cfg(feature = "gitoxide")]
+pub mod gitoxide;
+pub mod manifest;
+pub mod merge;
 pub mod run;
 pub mod shortlink;
-mod templates;
+pub mod templates;
+pub mod trust;
 pub mod ui;
+pub mod vars;
 pub mod vendors;
+
+pub use error::{Error, Result};
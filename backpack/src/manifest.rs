@@ -0,0 +1,205 @@
+//! The `.backpack.lock` manifest written into every generated folder: where it
+//! was generated from, with which answers, and what each generated file looked
+//! like, so that `bp update` can regenerate it later without touching local edits.
+use crate::config::ProjectSetupActions;
+use crate::data::Transport;
+use crate::{Error, Result};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = ".backpack.lock";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// the shortlink or project name generated from
+    pub shortlink: String,
+
+    #[serde(default)]
+    #[serde(rename = "ref")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gref: Option<String>,
+
+    /// the resolved revision, when the vendor resolved one
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subfolder: Option<String>,
+
+    /// fetched with git rather than as an archive
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_git: bool,
+
+    /// the transport explicitly selected when generating
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+
+    /// swap keys and the values they were replaced with
+    #[serde(default)]
+    pub swaps: BTreeMap<String, String>,
+
+    /// answers given to action interactions
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+
+    /// sha256 of each generated file, by its path relative to the destination
+    #[serde(default)]
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+    /// The manifest file for a generated folder
+    pub fn path(folder: &Path) -> PathBuf {
+        folder.join(MANIFEST_FILE)
+    }
+
+    /// Load the manifest of a generated folder
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no manifest or it cannot be parsed
+    pub fn load(folder: &Path) -> Result<Self> {
+        let path = Self::path(folder);
+        let text = fs::read_to_string(&path).map_err(|_| {
+            Error::config(format!(
+                "no {} found in '{}', was it generated by backpack?",
+                MANIFEST_FILE,
+                folder.display()
+            ))
+        })?;
+        serde_yaml::from_str(&text).map_err(|e| Error::from(e).in_file(&path))
+    }
+
+    /// Write the manifest into a generated folder
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if it cannot be written
+    pub fn save(&self, folder: &Path) -> Result<()> {
+        fs::write(Self::path(folder), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Record the current content of a generated file, `file` being inside `folder`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `file` cannot be read
+    pub fn record(&mut self, folder: &Path, file: &Path) -> Result<()> {
+        let relative = file.strip_prefix(folder).unwrap_or(file);
+        self.files.insert(relative.to_path_buf(), hash_file(file)?);
+        Ok(())
+    }
+
+    /// Is `file` (inside `folder`) as it was generated? Files that were not generated
+    /// are never pristine.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `file` cannot be read
+    pub fn is_pristine(&self, folder: &Path, file: &Path) -> Result<bool> {
+        let relative = file.strip_prefix(folder).unwrap_or(file);
+        match self.files.get(relative) {
+            Some(hash) => Ok(*hash == hash_file(file)?),
+            None => Ok(false),
+        }
+    }
+
+    /// Was `file` (inside `folder`) generated before?
+    pub fn generated(&self, folder: &Path, file: &Path) -> bool {
+        let relative = file.strip_prefix(folder).unwrap_or(file);
+        self.files.contains_key(relative)
+    }
+
+    /// Swap in the recorded values, so that regenerating gives the same content
+    pub fn pin_swaps(&self, setup: &mut ProjectSetupActions) {
+        for swap in setup.swaps.iter_mut().flatten() {
+            if let Some(val) = self.swaps.get(&swap.key) {
+                swap.val = Some(val.clone());
+                swap.val_template = None;
+            }
+        }
+    }
+}
+
+fn hash_file(file: &Path) -> Result<String> {
+    let content = fs::read(file)?;
+    Ok(format!("{:x}", Sha256::digest(content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::Swap;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_roundtrip_and_pristine() {
+        let folder = tempfile::tempdir().unwrap();
+        let file = folder.path().join("src/main.rs");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "fn main() {}").unwrap();
+
+        let mut manifest = Manifest {
+            shortlink: "user/repo".to_string(),
+            revision: Some("abc123".to_string()),
+            is_git: true,
+            transport: Some(Transport::Ssh),
+            ..Manifest::default()
+        };
+        manifest.record(folder.path(), &file).unwrap();
+        assert!(manifest.files.contains_key(Path::new("src/main.rs")));
+        manifest.save(folder.path()).unwrap();
+
+        let loaded = Manifest::load(folder.path()).unwrap();
+        assert_eq!(loaded, manifest);
+        assert!(loaded.is_pristine(folder.path(), &file).unwrap());
+
+        fs::write(&file, "fn main() { edited() }").unwrap();
+        assert!(!loaded.is_pristine(folder.path(), &file).unwrap());
+    }
+
+    #[test]
+    fn test_missing_manifest() {
+        let folder = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Manifest::load(folder.path()),
+            Err(Error::Config { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pin_swaps() {
+        let manifest = Manifest {
+            swaps: BTreeMap::from([("NAME".to_string(), "recorded".to_string())]),
+            ..Manifest::default()
+        };
+        let mut setup = ProjectSetupActions {
+            swaps: Some(vec![
+                Swap {
+                    key: "NAME".to_string(),
+                    val_template: Some("{{name}}".to_string()),
+                    ..Swap::default()
+                },
+                Swap {
+                    key: "OTHER".to_string(),
+                    val: Some("kept".to_string()),
+                    ..Swap::default()
+                },
+            ]),
+            ..ProjectSetupActions::default()
+        };
+        manifest.pin_swaps(&mut setup);
+        let swaps = setup.swaps.unwrap();
+        assert_eq!(swaps[0].val.as_deref(), Some("recorded"));
+        assert_eq!(swaps[0].val_template, None);
+        assert_eq!(swaps[1].val.as_deref(), Some("kept"));
+    }
+}
@@ -0,0 +1,161 @@
+//! Three-way merging of a template's new content into a file that was generated
+//! from it before, and edited locally since.
+//!
+//! Once merging is asked for, every generated text file's content is kept as the
+//! *base* for the next merge, in the generated folder next to its manifest, so it
+//! moves along with the project. Folders that never merged keep no bases.
+use crate::{Error, Result};
+use diffy::{ConflictStyle, MergeOptions};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BASE_FOLDER: &str = ".backpack.base";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MergeOutcome {
+    /// local edits and template changes were combined
+    Clean,
+    /// both changed the same lines, conflict markers were written
+    Conflict,
+    /// binary content cannot be merged, the local file was kept
+    Binary,
+}
+
+/// Where the previously generated content of each file in a generated folder is kept
+pub struct BaseStore {
+    root: PathBuf,
+    folder: PathBuf,
+}
+
+impl BaseStore {
+    /// The bases of the files generated into `root`
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            folder: root.join(BASE_FOLDER),
+        }
+    }
+
+    /// Are bases kept for this folder already?
+    pub fn exists(&self) -> bool {
+        self.folder.is_dir()
+    }
+
+    /// The base file for `file`, mirroring its path relative to the generated folder
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `file` is not in the generated folder
+    pub fn path(&self, file: &Path) -> Result<PathBuf> {
+        let relative = file
+            .strip_prefix(&self.root)
+            .map_err(|_| Error::UnsafePath {
+                path: file.to_path_buf(),
+                cause: format!("not in '{}'", self.root.display()),
+            })?;
+        Ok(self.folder.join(relative))
+    }
+
+    /// The content `file` was last generated with, if it was generated before
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the base exists but cannot be read
+    pub fn read(&self, file: &Path) -> Result<Option<Vec<u8>>> {
+        let base = self.path(file)?;
+        if base.is_file() {
+            Ok(Some(fs::read(base)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Remember the content `file` was generated with
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the base cannot be written
+    pub fn write(&self, file: &Path, content: &[u8]) -> Result<()> {
+        let base = self.path(file)?;
+        if let Some(parent) = base.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(base, content)?;
+        Ok(())
+    }
+}
+
+/// Merge the changes from `base` to `template` into `local`. A missing base merges
+/// as if both sides added the whole file.
+pub fn three_way(base: Option<&str>, local: &str, template: &str) -> (String, MergeOutcome) {
+    let mut opts = MergeOptions::new();
+    opts.set_conflict_style(ConflictStyle::Merge);
+    match opts.merge(base.unwrap_or_default(), local, template) {
+        Ok(merged) => (merged, MergeOutcome::Clean),
+        Err(conflicted) => (conflicted, MergeOutcome::Conflict),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_three_way_clean() {
+        let base = "one\ntwo\nthree\n";
+        let local = "one\ntwo, edited locally\nthree\n";
+        let template = "one\ntwo\nthree\nfour from template\n";
+        assert_eq!(
+            three_way(Some(base), local, template),
+            (
+                "one\ntwo, edited locally\nthree\nfour from template\n".to_string(),
+                MergeOutcome::Clean
+            )
+        );
+    }
+
+    #[test]
+    fn test_three_way_conflict() {
+        let base = "one\ntwo\nthree\n";
+        let local = "one\nlocal two\nthree\n";
+        let template = "one\ntemplate two\nthree\n";
+        assert_eq!(
+            three_way(Some(base), local, template),
+            (
+                "one\n<<<<<<< ours\nlocal two\n=======\ntemplate two\n>>>>>>> theirs\nthree\n"
+                    .to_string(),
+                MergeOutcome::Conflict
+            )
+        );
+    }
+
+    #[test]
+    fn test_three_way_no_base() {
+        assert_eq!(
+            three_way(None, "same\n", "same\n"),
+            ("same\n".to_string(), MergeOutcome::Clean)
+        );
+        assert_eq!(
+            three_way(None, "local\n", "template\n").1,
+            MergeOutcome::Conflict
+        );
+    }
+
+    #[test]
+    fn test_base_store_roundtrip() {
+        let project = tempfile::tempdir().unwrap();
+        let store = BaseStore::new(project.path());
+        let file = project.path().join("src/main.rs");
+
+        assert_eq!(store.read(&file).unwrap(), None);
+        store.write(&file, b"fn main() {}").unwrap();
+        assert_eq!(store.read(&file).unwrap(), Some(b"fn main() {}".to_vec()));
+        assert_eq!(
+            store.path(&file).unwrap(),
+            project.path().join(BASE_FOLDER).join("src/main.rs")
+        );
+        assert!(store.path(Path::new("/elsewhere/main.rs")).is_err());
+    }
+}
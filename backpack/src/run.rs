@@ -1,19 +1,109 @@
-use crate::config::Config;
-use crate::content::{Coordinate, Deployer};
-use crate::data::{CopyMode, Opts};
+use crate::config::{Config, LocalProjectConfig, ProjectSetupActions};
+use crate::content::{self, Coordinate, Deployer, Plan};
+use crate::data::{Assets, Location, Opts};
 use crate::fetch::Fetcher;
-use crate::git::{self};
+use crate::git::{self, GitProvider, RemoteInfo};
+use crate::manifest::Manifest;
+use crate::merge::BaseStore;
 use crate::shortlink::Shortlink;
-use crate::ui::Prompt;
-use anyhow::{Context, Result};
+use crate::templates::{CopyResult, Swapper};
+use crate::trust::{self, TrustStore};
+use crate::ui::{Headless, Prompt, Prompter};
+use crate::vars::{self, Builtins};
+use crate::Result;
+use interactive_actions::data::ActionResult;
 use interactive_actions::ActionRunner;
 use requestty_ui::events::KeyEvent;
+use serde::Serialize;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tracing::warn;
 
-#[derive(Default)]
 pub struct Runner {
-    git: Box<git::GitCmd>,
+    git: Box<dyn GitProvider>,
+    cache_path: Option<PathBuf>,
+    config: Option<Config>,
+    prompt: RefCell<Option<Box<dyn Prompter>>>,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        RunnerBuilder::default().build()
+    }
+}
+
+/// Builds a [`Runner`], for when embedding backpack with your own git provider,
+/// cache location, configuration or prompt.
+#[derive(Default)]
+pub struct RunnerBuilder {
+    git: Option<Box<dyn GitProvider>>,
+    cache_path: Option<PathBuf>,
+    config: Option<Config>,
+    prompt: Option<Box<dyn Prompter>>,
+}
+
+impl RunnerBuilder {
+    /// Use this git provider instead of the default one (see [`git::provider`])
+    #[must_use]
+    pub fn git(mut self, git: Box<dyn GitProvider>) -> Self {
+        self.git = Some(git);
+        self
+    }
+
+    /// Cache fetched archives here instead of the global cache folder
+    #[must_use]
+    pub fn cache_path(mut self, cache_path: impl Into<PathBuf>) -> Self {
+        self.cache_path = Some(cache_path.into());
+        self
+    }
+
+    /// Use this configuration instead of loading one from disk.
+    /// A `config_file` given in [`Opts`] still takes precedence.
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Ask and tell the user things through this prompt instead of the terminal
+    #[must_use]
+    pub fn prompt(mut self, prompt: Box<dyn Prompter>) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
+    pub fn build(self) -> Runner {
+        Runner {
+            git: self.git.unwrap_or_else(git::provider),
+            cache_path: self.cache_path,
+            config: self.config,
+            prompt: RefCell::new(self.prompt),
+        }
+    }
+}
+
+/// What a run did: where it generated from and to, and everything that happened on the way.
+#[derive(Debug, Serialize)]
+pub struct GenerationReport {
+    /// the shortlink or project name that was asked for
+    pub shortlink: String,
+    pub location: Location,
+    pub assets: Assets,
+    /// the ref and revision generated from, when known
+    pub revision: Option<RemoteInfo>,
+    pub destination: PathBuf,
+    pub files: Vec<CopyResult>,
+    pub actions: Vec<ActionResult>,
+    pub vars: BTreeMap<String, String>,
+    /// the user declined generating, nothing was written
+    pub cancelled: bool,
+    /// what would have been generated, for a dry run
+    pub plan: Option<Plan>,
+    /// files an update left alone, because they were edited or removed since generated
+    pub kept: Vec<PathBuf>,
+    /// keys of swaps that did not replace anything
+    pub unmatched_swaps: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -23,13 +113,49 @@ pub struct RunnerEvents {
 }
 
 impl Runner {
+    pub fn builder() -> RunnerBuilder {
+        RunnerBuilder::default()
+    }
+
     /// Run the workflow with progress
     ///
     /// # Errors
     ///
     /// This function will return an error if anything in the workflow failed
-    pub fn run(&self, shortlink: Option<&str>, dest: Option<&str>, opts: &Opts) -> Result<()> {
-        self.run_workflow(shortlink, dest, opts, None)
+    pub fn run(
+        &self,
+        shortlink: Option<&str>,
+        dest: Option<&str>,
+        opts: &Opts,
+    ) -> Result<GenerationReport> {
+        self.run_workflow(shortlink, dest, opts, None, None)
+    }
+
+    /// Update a folder generated before: fetch the latest of what it was generated
+    /// from, the way it was fetched then, and regenerate with the answers recorded
+    /// in its manifest. Only files that were not edited since are touched, and no
+    /// action commands run again.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there's no manifest, or anything in the workflow failed
+    pub fn update(&self, dest: Option<&str>, opts: &Opts) -> Result<GenerationReport> {
+        let dest = dest.unwrap_or(".");
+        let previous = Manifest::load(Path::new(dest))?;
+        // fetch the way the folder was generated, unless asked otherwise
+        let opts = Opts {
+            no_cache: true,
+            is_git: opts.is_git || previous.is_git,
+            transport: opts.transport.or(previous.transport),
+            ..opts.clone()
+        };
+        self.run_workflow(
+            Some(previous.shortlink.clone().as_str()),
+            Some(dest),
+            &opts,
+            None,
+            Some(previous),
+        )
     }
 
     /// Run the workflow with progress and synthetic test events
@@ -43,8 +169,8 @@ impl Runner {
         dest: Option<&str>,
         opts: &Opts,
         events: &RunnerEvents,
-    ) -> Result<()> {
-        self.run_workflow(shortlink, dest, opts, Some(events))
+    ) -> Result<GenerationReport> {
+        self.run_workflow(shortlink, dest, opts, Some(events), None)
     }
 
     fn run_workflow(
@@ -53,37 +179,48 @@ impl Runner {
         dest: Option<&str>,
         opts: &Opts,
         events: Option<&RunnerEvents>,
-    ) -> Result<()> {
-        // load from direct file, or magically load from 'local' then 'global', then default
-        let config = opts.config_file.as_ref().map_or_else(
-            || Config::load_or_default().context("could not load configuration"),
-            |f| Config::from_path(Path::new(f)),
-        )?;
-
-        let config = config;
+        previous: Option<Manifest>,
+    ) -> Result<GenerationReport> {
+        // load from direct file, or use the one we were built with, or magically load from 'local' then 'global', then default
+        let config = match (opts.config_file.as_ref(), self.config.as_ref()) {
+            (Some(f), _) => Config::from_path(Path::new(f))?,
+            (None, Some(config)) => config.clone(),
+            (None, None) => Config::load_or_default()?,
+        };
 
-        let prompt = &mut Prompt::build(&config, opts.show_progress, events);
+        let mut terminal_prompt = Prompt::build(&config, opts.show_progress, events);
+        let mut headless_prompt = Headless::new(&config, opts.show_progress);
+        let mut custom_prompt = self.prompt.borrow_mut();
+        let prompt: &mut dyn Prompter = match custom_prompt.as_mut() {
+            Some(custom) => custom.as_mut(),
+            None if opts.headless => &mut headless_prompt,
+            None => &mut terminal_prompt,
+        };
 
         let (shortlink, dest, should_confirm) = prompt.fill_missing(shortlink, dest, &opts.mode)?;
 
         let sl = Shortlink::new(&config, self.git.as_ref());
 
-        let mut vars: BTreeMap<String, String> = BTreeMap::new();
+        // an update answers with what was answered the last time, unless answered anew
+        let mut vars: BTreeMap<String, String> = previous
+            .as_ref()
+            .map(|previous| previous.vars.clone())
+            .unwrap_or_default();
+        vars.extend(opts.vars.clone());
 
         prompt.say_resolving();
-        let (location, assets) = sl.resolve(&shortlink, opts.is_git)?;
+        let (location, assets) = sl.resolve(&shortlink, opts.is_git, opts.transport)?;
 
-        let cached_path = Config::global_cache_folder()?;
+        let cached_path = match self.cache_path {
+            Some(ref cache_path) => cache_path.clone(),
+            None => Config::global_cache_folder()?,
+        };
         let fetcher = Fetcher::new(self.git.as_ref(), cached_path.as_path());
 
         prompt.say_fetching();
         let (source, remove_source) = fetcher.fetch(&location, &assets, opts.no_cache)?;
 
-        let project_setup = sl.setup_actions(&shortlink);
-
         let mut action_runner = build_runner(events);
-        let mut deployer = Deployer::new(&mut action_runner);
-
         let coords = Coordinate::new(
             source.as_path(),
             dest.as_deref().map(Path::new),
@@ -91,6 +228,94 @@ impl Runner {
             remove_source,
         )?;
 
+        let mut deployer = Deployer::new(&mut action_runner);
+        // bases are kept next to the manifest, a single file has neither. They're only
+        // kept once merging is asked for, and from then on kept up to date.
+        let bases = BaseStore::new(&coords.to);
+        if !coords.is_file && (opts.merge || bases.exists()) {
+            deployer = deployer.with_bases(bases);
+        }
+        if let Some(previous) = previous.as_ref() {
+            deployer = deployer.with_previous(previous.clone());
+        }
+
+        // a template may ship its own setup and ignore file, in the subfolder we take or at its root
+        let template_folders = [coords.from.as_path(), source.as_path()];
+        let template_ignore =
+            content::read_ignore_file(&template_folders)?.map(|exclude| ProjectSetupActions {
+                exclude: Some(exclude),
+                ..ProjectSetupActions::default()
+            });
+        let template_setup = ProjectSetupActions::merge(
+            template_ignore,
+            LocalProjectConfig::find_in(&template_folders)?
+                .map(|local| ProjectSetupActions::from(&local.project)),
+        );
+        let vendor = sl.vendor(&shortlink)?;
+        // a template's own commands run once it's trusted; planning and updating run nothing
+        let template_setup = match template_setup {
+            Some(setup) if !opts.dry_run && previous.is_none() => Some(trusted_setup(
+                setup,
+                &config,
+                vendor,
+                &location,
+                assets.remote.as_ref().map(|r| r.revision.as_str()),
+                opts,
+                prompt,
+            )?),
+            setup => setup,
+        };
+        let mut project_setup =
+            ProjectSetupActions::merge(template_setup, sl.setup_actions(&shortlink));
+        // an update regenerates files, the commands ran when the folder was generated
+        if previous.is_some() {
+            project_setup = project_setup.map(trust::without_commands);
+        }
+        if let (Some(previous), Some(setup)) = (previous.as_ref(), project_setup.as_mut()) {
+            previous.pin_swaps(setup);
+        }
+
+        // what's known before anything is asked; recorded answers of an update come first
+        let builtins = Builtins {
+            shortlink: &shortlink,
+            vendor,
+            location: &location,
+            remote: assets.remote.as_ref(),
+            dest: if coords.is_file {
+                coords.to.parent().unwrap_or_else(|| Path::new("."))
+            } else {
+                coords.to.as_path()
+            },
+            git_user_name: self.git.config_value("user.name"),
+            git_user_email: self.git.config_value("user.email"),
+        };
+        for (key, val) in builtins.vars() {
+            vars.entry(key).or_insert(val);
+        }
+
+        let mut report = GenerationReport {
+            shortlink: shortlink.clone(),
+            revision: assets.remote.clone(),
+            location,
+            assets,
+            destination: coords.to.clone(),
+            files: vec![],
+            actions: vec![],
+            vars: BTreeMap::new(),
+            cancelled: false,
+            plan: None,
+            kept: vec![],
+            unmatched_swaps: vec![],
+        };
+
+        if opts.dry_run {
+            let plan = deployer.plan(&coords, project_setup.as_ref(), &mut vars, opts)?;
+            prompt.say_plan(&plan);
+            report.plan = Some(plan);
+            report.vars = vars;
+            return Ok(report);
+        }
+
         // confirm
         if !opts.always_yes
             && should_confirm
@@ -101,15 +326,98 @@ impl Runner {
             ))?
         {
             // bail out, user won't confirm
-            return Ok(());
+            report.cancelled = true;
+            return Ok(report);
         }
 
         prompt.say_unpacking();
+        let is_file = coords.is_file;
+        let swaps = project_setup.as_ref().and_then(|p| p.swaps.clone());
         let (files, maybe_actions) =
             deployer.deploy(coords, project_setup, &mut vars, opts, prompt)?;
 
-        prompt.say_done(&files, maybe_actions.as_ref());
-        Ok(())
+        prompt.say_done(&files, maybe_actions.as_ref(), deployer.unmatched_swaps());
+
+        // a single file has no folder to keep a manifest in
+        if !is_file {
+            let mut manifest = previous.unwrap_or_default();
+            manifest.shortlink = shortlink;
+            manifest.gref = report
+                .revision
+                .as_ref()
+                .map(|r| r.ref_.clone())
+                .or_else(|| report.location.gref.clone());
+            manifest.revision = report.revision.as_ref().map(|r| r.revision.clone());
+            manifest.subfolder = report.location.subfolder.clone();
+            manifest.is_git = opts.is_git;
+            manifest.transport = opts.transport;
+            manifest.swaps = Swapper::with_vars(swaps.as_ref(), &vars)?
+                .swaps()
+                .iter()
+                .filter_map(|s| s.val.clone().map(|val| (s.key.clone(), val)))
+                .collect();
+            manifest.vars = vars
+                .iter()
+                .filter(|(key, _)| !vars::is_builtin(key))
+                .map(|(key, val)| (key.clone(), val.clone()))
+                .collect();
+            // merged files carry local edits, they are not as generated
+            for file in files.iter().filter(|f| f.merge.is_none()) {
+                manifest.record(&report.destination, &file.dest)?;
+            }
+            manifest.save(&report.destination)?;
+        }
+
+        report.kept = deployer.kept().to_vec();
+        report.unmatched_swaps = deployer.unmatched_swaps().to_vec();
+
+        report.files = files;
+        report.actions = maybe_actions.unwrap_or_default();
+        report.vars = vars;
+        Ok(report)
+    }
+}
+
+/// A template's own commands run only once the user trusted the template at its
+/// revision with these commands, and never if it's from a host the configuration doesn't trust. What
+/// isn't trusted is left out, the template's interactions stay.
+fn trusted_setup(
+    setup: ProjectSetupActions,
+    config: &Config,
+    vendor: &str,
+    location: &Location,
+    revision: Option<&str>,
+    opts: &Opts,
+    prompt: &mut dyn Prompter,
+) -> Result<ProjectSetupActions> {
+    let commands = trust::commands(&setup);
+    // local templates are the user's own files
+    if commands.is_empty() || vendor == "local" {
+        return Ok(setup);
+    }
+    let host_trusted = config
+        .trust
+        .as_ref()
+        .and_then(|trust| trust.hosts.as_ref())
+        .is_none_or(|hosts| hosts.contains(&location.domain));
+    if !host_trusted {
+        warn!(
+            "not running actions of a template from untrusted host '{}'",
+            location.domain
+        );
+        return Ok(trust::without_commands(setup));
+    }
+
+    let repo = format!("{}/{}", location.domain, location.path.trim_matches('/'));
+    let fingerprint = trust::fingerprint(revision, &commands);
+    let mut store = TrustStore::load(&Config::global_config_folder()?)?;
+    if opts.trust || store.is_trusted(&repo, &fingerprint) {
+        Ok(setup)
+    } else if prompt.confirm_trust(&repo, &commands)? {
+        store.trust(&repo, &fingerprint)?;
+        Ok(setup)
+    } else {
+        Ok(trust::without_commands(setup))
     }
 }
 
@@ -1,14 +1,13 @@
 use std::path::Path;
 
+use crate::Result;
 use crate::{
-    config::{Config, ProjectSetupActions},
-    data::{Assets, Location},
+    config::{Config, ProjectAction, ProjectSetupActions},
+    data::{Assets, Location, Transport},
     git::GitProvider,
     templates::Swap,
     vendors::{LocalGit, Vendor, Vendors},
 };
-use anyhow::Result as AnyResult;
-use interactive_actions::data::Action;
 use lazy_static::lazy_static;
 use regex::Regex;
 use tracing;
@@ -23,16 +22,17 @@ fn expand(
     shortlink: &str,
     is_git: bool,
     vendors: &Vendors<'_>,
-) -> AnyResult<(Box<dyn Vendor>, Location)> {
+) -> anyhow::Result<(Box<dyn Vendor>, Location)> {
     let res = if shortlink.starts_with("https://") {
         //https://github.com/jondot/hygen/-/foobar
 
         let url = Url::parse(shortlink)?;
-        let vendor = vendors.lookup(
-            url.domain()
-                .ok_or_else(|| anyhow::anyhow!("domain is missing"))?,
-        )?;
-        (vendor, Location::from(&url, is_git)?)
+        let domain = url
+            .domain()
+            .ok_or_else(|| anyhow::anyhow!("domain is missing"))?;
+        let mut location = Location::from(&url, is_git)?;
+        vendors.configure(domain, &mut location);
+        (vendors.lookup(domain)?, location)
     } else if Path::new(shortlink).exists() {
         let p = Path::new(shortlink);
         let local_git_url = Url::parse(&format!("file://{}.git", p.to_string_lossy()))?;
@@ -43,25 +43,24 @@ fn expand(
             .get(1)
             .ok_or_else(|| anyhow::anyhow!("parse failed: no domain"))?
             .as_str();
-        (
-            vendors.lookup(domain)?,
-            Location::from(
-                &Url::parse(&format!(
-                    "https://{}/{}",
-                    domain,
-                    caps.get(2)
-                        .ok_or_else(|| anyhow::anyhow!("parse failed: no path"))?
-                        .as_str()
-                ))?,
-                is_git,
-            )?,
-        )
-    } else if let Some(caps) = RE_VENDOR.captures(shortlink) {
-        let vendor = vendors.lookup(
-            caps.get(1)
-                .ok_or_else(|| anyhow::anyhow!("parse failed: no vendor"))?
-                .as_str(),
+        let mut location = Location::from(
+            &Url::parse(&format!(
+                "https://{}/{}",
+                domain,
+                caps.get(2)
+                    .ok_or_else(|| anyhow::anyhow!("parse failed: no path"))?
+                    .as_str()
+            ))?,
+            is_git,
         )?;
+        vendors.configure(domain, &mut location);
+        (vendors.lookup(domain)?, location)
+    } else if let Some(caps) = RE_VENDOR.captures(shortlink) {
+        let prefix = caps
+            .get(1)
+            .ok_or_else(|| anyhow::anyhow!("parse failed: no vendor"))?
+            .as_str();
+        let vendor = vendors.lookup(prefix)?;
         let url = Url::parse(&format!(
             "https://{}/{}",
             vendor.base(),
@@ -69,12 +68,15 @@ fn expand(
                 .ok_or_else(|| anyhow::anyhow!("parse failed: no path"))?
                 .as_str()
         ))?;
-
-        (vendor, Location::from(&url, is_git)?)
+        let mut location = Location::from(&url, is_git)?;
+        vendors.configure(prefix, &mut location);
+        (vendor, location)
     } else {
         let vendor = vendors.lookup("")?;
         let url = Url::parse(&format!("https://{}/{shortlink}", vendor.base()))?;
-        (vendor, Location::from(&url, is_git)?)
+        let mut location = Location::from(&url, is_git)?;
+        vendors.configure("", &mut location);
+        (vendor, location)
     };
 
     Ok(res)
@@ -90,22 +92,57 @@ impl<'a> Shortlink<'a> {
         Self { config, git }
     }
 
+    /// Resolve a shortlink or a project name into a location and its assets.
+    /// A `transport` given here overrides the one set on the project, which in turn
+    /// overrides the one set on the vendor.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the shortlink cannot be expanded or resolved
     #[tracing::instrument(name = "shortlink_resolve", skip_all, err)]
-    pub fn resolve(&self, shortlink: &str, is_git: bool) -> AnyResult<(Location, Assets)> {
+    pub fn resolve(
+        &self,
+        shortlink: &str,
+        is_git: bool,
+        transport: Option<Transport>,
+    ) -> Result<(Location, Assets)> {
+        let (vendor, mut location) = self.expand(shortlink, is_git)?;
+        let project = self.config.project(shortlink);
+        if let Some(transport) = transport.or_else(|| project.and_then(|p| p.transport)) {
+            location.transport = Some(transport);
+        }
+        let assets = vendor.resolve(&location, self.git)?;
+        Ok((location, assets))
+    }
+
+    /// The kind of vendor a shortlink or project name resolves with, e.g. `github`,
+    /// without accessing the network
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the shortlink cannot be expanded
+    pub fn vendor(&self, shortlink: &str) -> Result<&'static str> {
+        Ok(self.expand(shortlink, false)?.0.kind())
+    }
+
+    fn expand(&self, shortlink: &str, is_git: bool) -> Result<(Box<dyn Vendor>, Location)> {
         // try to get better settings from projects config:
-        let (shortlink, is_git) = self.config.project(shortlink).map_or_else(
+        let project = self.config.project(shortlink);
+        let (shortlink, is_git) = project.map_or_else(
             || (shortlink, is_git),
             |project| (project.shortlink.as_str(), project.is_git.unwrap_or(false)),
         );
 
-        // expand and resolve
         let vendors = Vendors::new(self.config.vendors.as_ref());
         let (vendor, location) = expand(shortlink, is_git, &vendors)?;
-        let assets = vendor.resolve(&location, self.git)?;
-        Ok((location, assets))
+        // before anything touches the network
+        if let Some(policy) = self.config.policy.as_ref() {
+            policy.check(shortlink, vendor.kind(), &location.domain)?;
+        }
+        Ok((vendor, location))
     }
 
-    pub fn actions(&self, shortlink: &str) -> Option<&'a Vec<Action>> {
+    pub fn actions(&self, shortlink: &str) -> Option<&'a Vec<ProjectAction>> {
         self.config
             .project(shortlink)
             .and_then(|project| project.actions.as_ref())
@@ -119,10 +156,7 @@ impl<'a> Shortlink<'a> {
     pub fn setup_actions(&self, shortlink: &str) -> Option<ProjectSetupActions> {
         self.config
             .project(shortlink)
-            .map(|project| ProjectSetupActions {
-                actions: project.actions.clone(),
-                swaps: project.swaps.clone(),
-            })
+            .map(ProjectSetupActions::from)
     }
 }
 
@@ -182,6 +216,126 @@ vendors:
         assert_debug_snapshot!((link.clone(), expand(&link, false, &vendors)));
     }
 
+    #[test]
+    fn test_vendor_transport() {
+        let config = Config::from_text(
+            r###"
+vendors:
+  default:
+    kind: gitlab
+    base: my.gitlab.com:8443
+    transport: ssh
+    ssh_port: 2222
+  custom:
+    glh:
+      kind: gitlab
+      base: my.gitlab.com:8443
+      transport: https
+    corp:
+      kind: gitlab
+      base: oauth2@git.corp.com:8443
+      transport: ssh
+      ssh_port: 2222
+      ssh_user: deploy
+"###,
+        )
+        .unwrap();
+        let vendors = Vendors::new(config.vendors.as_ref());
+
+        let (_, location) = expand("glh:group/repo", true, &vendors).unwrap();
+        assert_eq!(location.transport, Some(Transport::Https));
+        assert_eq!(
+            location.clone_url(),
+            "https://my.gitlab.com:8443/group/repo.git"
+        );
+
+        let (_, location) = expand("group/repo", true, &vendors).unwrap();
+        assert_eq!(location.transport, Some(Transport::Ssh));
+        assert_eq!(
+            location.clone_url(),
+            "ssh://git@my.gitlab.com:2222/group/repo.git"
+        );
+        assert_eq!(location.web_url(), "https://my.gitlab.com:8443/group/repo");
+
+        // full urls to a configured host get its settings too
+        for link in [
+            "https://git.corp.com:8443/group/repo",
+            "git@git.corp.com:group/repo",
+        ] {
+            let (vendor, location) = expand(link, true, &vendors).unwrap();
+            assert_eq!(vendor.kind(), "gitlab");
+            assert_eq!(location.transport, Some(Transport::Ssh));
+            assert_eq!(
+                location.clone_url(),
+                "ssh://deploy@git.corp.com:2222/group/repo.git"
+            );
+        }
+    }
+
+    /// Fails any attempt to reach the network
+    struct NoNetwork;
+    impl GitProvider for NoNetwork {
+        fn shallow_clone(&self, _location: &Location, _out: &str) -> Result<()> {
+            panic!("network access")
+        }
+        fn ls_remote(&self, _location: &Location) -> Result<Vec<crate::git::RemoteInfo>> {
+            panic!("network access")
+        }
+        fn get_ref_or_default(&self, _location: &Location) -> Result<crate::git::RemoteInfo> {
+            panic!("network access")
+        }
+        fn get_local_url(&self) -> Result<String> {
+            panic!("network access")
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let config = Config::from_text(
+            r###"
+projects:
+  sneaky:
+    shortlink: https://bitbucket.org/org/repo
+    is_git: true
+vendors:
+  custom:
+    corp:
+      kind: gitlab
+      base: git.corp.com
+policy:
+  allow:
+    domains: [github.com, "*.corp.com"]
+  deny:
+    vendors: [gist]
+"###,
+        )
+        .unwrap();
+        let sl = Shortlink::new(&config, &NoNetwork);
+        let blocked = |link: &str| match sl.resolve(link, true, None) {
+            Err(crate::Error::Blocked { rule, .. }) => rule,
+            other => panic!("expected '{link}' to be blocked, got {other:?}"),
+        };
+
+        assert_eq!(
+            blocked("sneaky"),
+            "'bitbucket.org' is not in policy.allow.domains"
+        );
+        assert_eq!(
+            blocked("gl:org/repo"),
+            "'gitlab.com' is not in policy.allow.domains"
+        );
+        assert_eq!(blocked("gist:123"), "policy.deny.vendors has 'gist'");
+        assert_eq!(
+            sl.resolve("bb:org/repo", true, None)
+                .unwrap_err()
+                .to_string(),
+            "'bb:org/repo' is blocked by policy: 'bitbucket.org' is not in policy.allow.domains"
+        );
+
+        assert_eq!(sl.vendor("org/repo").unwrap(), "github");
+        assert_eq!(sl.vendor("corp:org/repo").unwrap(), "gitlab");
+    }
+
     #[rstest]
     fn test_smoke(
         #[values("", "gl:")] vendor: &str,
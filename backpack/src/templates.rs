@@ -1,9 +1,13 @@
+use crate::merge::MergeOutcome;
+use aho_corasick::AhoCorasick;
 use anyhow::{bail, Context, Result};
 use content_inspector::inspect;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use tera::Tera;
@@ -35,6 +39,18 @@ pub struct Swap {
     #[serde(rename = "kind")]
     #[serde(skip_serializing_if = "default")]
     pub kind: SwapKind,
+
+    /// `key` is a regex, and `val` may refer to its capture groups (`$1`, `${name}`)
+    #[serde(default)]
+    #[serde(rename = "regex")]
+    #[serde(skip_serializing_if = "default")]
+    pub regex: bool,
+
+    /// fail generating if `key` matched nothing
+    #[serde(default)]
+    #[serde(rename = "required")]
+    #[serde(skip_serializing_if = "default")]
+    pub required: bool,
 }
 
 impl Swap {
@@ -42,7 +58,7 @@ impl Swap {
         match self.kind {
             SwapKind::Path | SwapKind::All => {
                 let pstr = p.display().to_string();
-                self.path.as_ref().map_or(true, |exp| exp.is_match(&pstr))
+                self.path.as_ref().is_none_or(|exp| exp.is_match(&pstr))
             }
             SwapKind::Content => false,
         }
@@ -52,7 +68,7 @@ impl Swap {
         match self.kind {
             SwapKind::Content | SwapKind::All => {
                 let pstr = p.display().to_string();
-                self.path.as_ref().map_or(true, |exp| exp.is_match(&pstr))
+                self.path.as_ref().is_none_or(|exp| exp.is_match(&pstr))
             }
             SwapKind::Path => false,
         }
@@ -70,14 +86,48 @@ pub enum SwapKind {
     Path,
 }
 
+/// Files rendered as Tera templates lose this suffix
+pub const TERA_SUFFIX: &str = ".tera";
+
 pub struct Swapper {
     swaps: Vec<Swap>,
+    /// all literal keys, with the index of their swap. Matches overlap, so that each
+    /// call picks among just the swaps that apply to it.
+    literals: Option<(AhoCorasick, Vec<usize>)>,
+    /// compiled keys of `regex` swaps, by swap index
+    regexes: BTreeMap<usize, Regex>,
+    /// indexes of the swaps that replaced something in what was written
+    matched: RefCell<BTreeSet<usize>>,
+    vars: tera::Context,
+    templates: Option<TemplateFiles>,
+}
+
+/// Which files are rendered as Tera templates, by their path relative to `from` (or `to`)
+struct TemplateFiles {
+    globs: GlobSet,
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl TemplateFiles {
+    fn matches(&self, root: &Path, p: &Path) -> bool {
+        // a single file is its own root
+        let relative = match p.strip_prefix(root) {
+            Ok(rel) if rel.as_os_str().is_empty() => p.file_name().map(Path::new),
+            Ok(rel) => Some(rel),
+            Err(_) => None,
+        };
+        relative.is_some_and(|rel| self.globs.is_match(rel))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct CopyResult {
     pub dest: PathBuf,
     pub op: SwapOp,
+    /// set when the file existed and was merged rather than overwritten
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merge: Option<MergeOutcome>,
 }
 #[derive(Debug, Clone, Serialize, Default)]
 pub enum SwapOp {
@@ -95,31 +145,199 @@ impl Swapper {
     pub fn with_vars(swaps: Option<&Vec<Swap>>, vars: &BTreeMap<String, String>) -> Result<Self> {
         let empty = vec![];
         let s = swaps.unwrap_or(&empty);
+        let swaps = Self::materialize_val_template(s, vars)?;
         Ok(Self {
-            swaps: Self::materialize_val_template(s, vars)?,
+            literals: Self::build_literals(&swaps),
+            regexes: Self::compile_regexes(&swaps)?,
+            swaps,
+            matched: RefCell::new(BTreeSet::new()),
+            vars: tera::Context::from_serialize(vars)?,
+            templates: None,
         })
     }
 
+    /// Render files matching `patterns` (globs relative to `from`) as Tera templates
+    /// with the vars as context, writing them without their [`TERA_SUFFIX`]
+    ///
+    /// # Errors
+    /// Return errors when a pattern is illegal
+    pub fn with_templates(mut self, patterns: &[String], from: &Path, to: &Path) -> Result<Self> {
+        if patterns.is_empty() {
+            return Ok(self);
+        }
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("bad render pattern '{pattern}'"))?,
+            );
+        }
+        self.templates = Some(TemplateFiles {
+            globs: builder.build()?,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(self)
+    }
+
+    fn is_template(&self, source: &Path) -> bool {
+        self.templates
+            .as_ref()
+            .is_some_and(|t| t.matches(&t.from, source))
+    }
+
+    /// The swaps, with their values filled in
+    pub fn swaps(&self) -> &[Swap] {
+        &self.swaps
+    }
+
+    /// Swaps that didn't replace anything in the paths and files written so far
+    pub fn unmatched(&self) -> Vec<&Swap> {
+        self.unmatched_in(&self.matched.borrow())
+    }
+
+    /// Swaps whose index is not in `matched`
+    pub fn unmatched_in(&self, matched: &BTreeSet<usize>) -> Vec<&Swap> {
+        self.swaps
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !matched.contains(idx))
+            .map(|(_, s)| s)
+            .collect()
+    }
+
+    /// Indexes of the swaps that replace something in path `dest` or in the content of `source`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` cannot be read
+    pub fn matches(&self, source: &Path, dest: &Path) -> Result<BTreeSet<usize>> {
+        let mut matched = self.swap_path(dest).1;
+        if let Some((_, hits)) = self.render(source)? {
+            matched.extend(hits);
+        }
+        Ok(matched.into_iter().collect())
+    }
+
+    /// Count the swaps of `source` as matched, once its content was written to `dest`
+    /// other than with [`Swapper::copy_to`], e.g. merged into an existing file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` cannot be read
+    pub fn record_written(&self, source: &Path, dest: &Path) -> Result<()> {
+        let matched = self.matches(source, dest)?;
+        self.matched.borrow_mut().extend(matched);
+        Ok(())
+    }
+
     pub fn path(&self, p: &Path) -> PathBuf {
-        let mut s = p.display().to_string();
-        for swap in self.swaps.iter().filter(|s| s.match_path(p)) {
-            if let Some(val) = swap.val.as_ref() {
-                s = s.replace(swap.key.as_str(), val);
+        self.swap_path(p).0
+    }
+
+    /// The swapped path, and the index of the swap behind each replacement
+    fn swap_path(&self, p: &Path) -> (PathBuf, Vec<usize>) {
+        let original = p.display().to_string();
+        let (s, hits) = self.replace_all(|swap| swap.match_path(p), &original);
+        let mut s = s.into_owned();
+        let is_template = self.templates.as_ref().is_some_and(|t| t.matches(&t.to, p));
+        if is_template {
+            if let Some(stripped) = s.strip_suffix(TERA_SUFFIX) {
+                s = stripped.to_string();
             }
         }
-        PathBuf::from(s)
+        (PathBuf::from(s), hits)
+    }
+
+    /// Keys of the swaps that would change path `p`
+    pub fn path_swaps(&self, p: &Path) -> Vec<&str> {
+        let original = p.display().to_string();
+        self.swaps
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.match_path(p) && s.val.is_some())
+            .filter(|(idx, s)| {
+                if s.regex {
+                    self.regexes
+                        .get(idx)
+                        .is_some_and(|re| re.is_match(&original))
+                } else {
+                    !s.key.is_empty() && original.contains(&s.key)
+                }
+            })
+            .map(|(_, s)| s.key.as_str())
+            .collect()
     }
 
-    pub fn render_content<'a>(content_swaps: &[&Swap], original: &'a str) -> (Cow<'a, str>, usize) {
-        let mut out: Cow<'_, str> = original.into();
-        let mut count = 0;
-        for swap in content_swaps {
-            if let Some(val) = swap.val.as_ref() {
-                count += out.matches(swap.key.as_str()).count();
-                out = out.replace(swap.key.as_str(), val).into();
+    /// Replace the content swaps that apply to `source` in `original`
+    pub fn render_content<'a>(&self, source: &Path, original: &'a str) -> (Cow<'a, str>, usize) {
+        let (out, hits) = self.replace_all(|swap| swap.match_content(source), original);
+        (out, hits.len())
+    }
+
+    /// Replace the keys of the swaps that `applies` to in `text` in a single pass,
+    /// returning the index of the swap behind each replacement. Where keys overlap the
+    /// leftmost match wins, then the longest, then the first swap listed. A replaced
+    /// value is never matched again by another key.
+    fn replace_all<'a>(
+        &self,
+        applies: impl Fn(&Swap) -> bool,
+        text: &'a str,
+    ) -> (Cow<'a, str>, Vec<usize>) {
+        // (start, end, swap index, replacement)
+        let mut hits: Vec<(usize, usize, usize, Cow<'_, str>)> = vec![];
+
+        if let Some((matcher, indexes)) = self.literals.as_ref() {
+            for m in matcher.find_overlapping_iter(text) {
+                let idx = indexes[m.pattern()];
+                let swap = &self.swaps[idx];
+                if applies(swap) {
+                    let val = swap.val.as_deref().unwrap_or_default();
+                    hits.push((m.start(), m.end(), idx, val.into()));
+                }
+            }
+        }
+
+        for (idx, re) in &self.regexes {
+            let swap = &self.swaps[*idx];
+            let Some(val) = swap.val.as_ref().filter(|_| applies(swap)) else {
+                continue;
+            };
+            for caps in re.captures_iter(text) {
+                let m = caps.get(0).expect("group 0 is always set");
+                if m.start() < m.end() {
+                    let mut replacement = String::new();
+                    caps.expand(val, &mut replacement);
+                    hits.push((m.start(), m.end(), *idx, replacement.into()));
+                }
             }
         }
-        (out, count)
+
+        if hits.is_empty() {
+            return (text.into(), vec![]);
+        }
+        hits.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| b.1.cmp(&a.1))
+                .then_with(|| a.2.cmp(&b.2))
+        });
+
+        let mut out = String::with_capacity(text.len());
+        let mut cursor = 0;
+        let mut replaced = vec![];
+        for (start, end, idx, replacement) in hits {
+            if start < cursor {
+                continue;
+            }
+            out.push_str(&text[cursor..start]);
+            out.push_str(&replacement);
+            cursor = end;
+            replaced.push(idx);
+        }
+        out.push_str(&text[cursor..]);
+        (out.into(), replaced)
     }
 
     /// Copy from `source` to `dest`, creating all folders if missing in `dest`
@@ -128,7 +346,7 @@ impl Swapper {
     ///
     /// This function will return an error if any of the `io` operations fail
     pub fn copy_to(&self, source: &Path, dest: &Path) -> Result<CopyResult> {
-        let swapped = self.path(dest);
+        let (swapped, path_hits) = self.swap_path(dest);
         let parent = swapped
             .parent()
             .ok_or_else(|| anyhow::anyhow!("cannot get parent for {:?}", swapped))?;
@@ -136,39 +354,103 @@ impl Swapper {
             fs::create_dir_all(parent)?;
         };
 
-        let content_swaps = self
-            .swaps
-            .iter()
-            .filter(|swap| swap.match_content(source))
-            .collect::<Vec<_>>();
+        let mut matched = self.matched.borrow_mut();
+        matched.extend(path_hits);
+        match self.render(source)? {
+            Some((contents, hits)) => {
+                fs::write(&swapped, contents.as_bytes())?;
+                let count = hits.len();
+                matched.extend(hits);
+                Ok(CopyResult {
+                    dest: swapped,
+                    op: SwapOp::Rendered(count),
+                    merge: None,
+                })
+            }
+            None => {
+                fs::copy(source, &swapped)?;
+                Ok(CopyResult {
+                    dest: swapped,
+                    op: SwapOp::Copied,
+                    merge: None,
+                })
+            }
+        }
+    }
+
+    /// What [`Swapper::copy_to`] would do, without writing anything
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` cannot be read
+    pub fn plan(&self, source: &Path, dest: &Path) -> Result<CopyResult> {
+        Ok(CopyResult {
+            dest: self.path(dest),
+            op: self
+                .render(source)?
+                .map_or(SwapOp::Copied, |(_, hits)| SwapOp::Rendered(hits.len())),
+            merge: None,
+        })
+    }
+
+    /// The text [`Swapper::copy_to`] would write for `source`, or `None` if it's binary
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` cannot be read
+    pub fn content(&self, source: &Path) -> Result<Option<String>> {
+        if let Some((contents, _)) = self.render(source)? {
+            return Ok(Some(contents));
+        }
+        let original = fs::read(source).with_context(|| format!("reading {}", source.display()))?;
+        Ok(String::from_utf8(original).ok())
+    }
+
+    /// The bytes [`Swapper::copy_to`] would write for `source`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `source` cannot be read
+    pub fn bytes(&self, source: &Path) -> Result<Vec<u8>> {
+        match self.render(source)? {
+            Some((contents, _)) => Ok(contents.into_bytes()),
+            None => Ok(fs::read(source)?),
+        }
+    }
+
+    /// Render `source` as a template and with its content swaps, with the index of the
+    /// swap behind each replacement, or `None` if it should be copied as-is
+    fn render(&self, source: &Path) -> Result<Option<(String, Vec<usize>)>> {
+        let is_template = self.is_template(source);
 
         // bail if no swaps
-        if content_swaps.is_empty() {
-            fs::copy(source, &swapped)?;
-            return Ok(CopyResult {
-                dest: swapped,
-                op: SwapOp::Copied,
-            });
+        if !self.swaps.iter().any(|swap| swap.match_content(source)) && !is_template {
+            return Ok(None);
         }
 
         // bail if text and has swaps
         let original = fs::read(source).with_context(|| format!("reading {}", source.display()))?;
         if inspect(&original).is_text() {
-            let read = String::from_utf8(original)?;
-            let (contents, count) = Self::render_content(&content_swaps, read.as_str());
-            fs::write(&swapped, contents.as_bytes())?;
-            return Ok(CopyResult {
-                dest: swapped,
-                op: SwapOp::Rendered(count),
-            });
+            let mut read = String::from_utf8(original)?;
+            if is_template {
+                read = self.render_template(source, &read)?;
+            }
+            let (contents, hits) =
+                self.replace_all(|swap| swap.match_content(source), read.as_str());
+            return Ok(Some((contents.into_owned(), hits)));
         }
 
         // turned out to be binary, we can't swap
-        fs::copy(source, &swapped)?;
-        Ok(CopyResult {
-            dest: swapped,
-            op: SwapOp::Copied,
-        })
+        Ok(None)
+    }
+
+    fn render_template(&self, source: &Path, text: &str) -> Result<String> {
+        let mut tera = Tera::default();
+        tera_text_filters::register_all(&mut tera);
+        let name = source.display().to_string();
+        tera.add_raw_template(&name, text)
+            .and_then(|()| tera.render(&name, &self.vars))
+            .map_err(|err| template_error(source, text, &err))
     }
 
     pub fn exists(&self, dest: &Path) -> bool {
@@ -197,6 +479,97 @@ impl Swapper {
             })
             .collect::<Result<Vec<_>>>()
     }
+
+    fn build_literals(swaps: &[Swap]) -> Option<(AhoCorasick, Vec<usize>)> {
+        let indexes = swaps
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !s.regex && !s.key.is_empty() && s.val.is_some())
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        (!indexes.is_empty()).then(|| {
+            let matcher = AhoCorasick::new(indexes.iter().map(|idx| swaps[*idx].key.as_str()));
+            (matcher, indexes)
+        })
+    }
+
+    fn compile_regexes(swaps: &[Swap]) -> Result<BTreeMap<usize, Regex>> {
+        swaps
+            .iter()
+            .enumerate()
+            .filter(|(_, swap)| swap.regex)
+            .map(|(idx, swap)| {
+                let re = Regex::new(&swap.key)
+                    .with_context(|| format!("bad regex in swap key '{}'", swap.key))?;
+                Ok((idx, re))
+            })
+            .collect()
+    }
+}
+
+/// Evaluate an `if:` condition over the vars: a Tera expression, with or without its
+/// `{{ }}`. It holds unless it renders to nothing or to `false`.
+///
+/// # Errors
+/// Return errors when the expression is illegal, or uses a variable that isn't set
+pub fn eval_condition(expr: &str, vars: &BTreeMap<String, String>) -> Result<bool> {
+    let template = if expr.contains("{{") || expr.contains("{%") {
+        expr.to_string()
+    } else {
+        format!("{{{{ {expr} }}}}")
+    };
+    let mut tera = Tera::default();
+    tera_text_filters::register_all(&mut tera);
+    let rendered = tera
+        .render_str(&template, &tera::Context::from_serialize(vars)?)
+        .map_err(|err| {
+            let cause = root_cause(&err);
+            // the one-off template's made-up name means nothing to the user
+            let cause = cause.split(" while rendering").next().unwrap_or_default();
+            anyhow::anyhow!("condition '{}': {}", expr, cause)
+        })?;
+    let rendered = rendered.trim();
+    Ok(!(rendered.is_empty() || rendered.eq_ignore_ascii_case("false")))
+}
+
+/// Tera wraps what went wrong in a chain of errors, the last one says it best
+fn root_cause(err: &tera::Error) -> String {
+    let mut cause: &dyn std::error::Error = err;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+/// Describe a Tera error by the file and line it happened in. Parse errors know
+/// their line, for render errors it's the first line mentioning what went wrong.
+fn template_error(file: &Path, text: &str, err: &tera::Error) -> anyhow::Error {
+    lazy_static::lazy_static! {
+        static ref PARSE_LINE: Regex = Regex::new(r"-->\s*(\d+):\d+").unwrap();
+        static ref QUOTED: Regex = Regex::new(r"`([^`]+)`").unwrap();
+    }
+    let cause = root_cause(err);
+
+    let line = PARSE_LINE
+        .captures(&cause)
+        .and_then(|caps| caps[1].parse::<usize>().ok())
+        .or_else(|| {
+            let quoted = QUOTED.captures(&cause)?;
+            text.lines()
+                .position(|line| line.contains(&quoted[1]))
+                .map(|idx| idx + 1)
+        });
+    let message = cause
+        .lines()
+        .filter(|l| l.trim_start().starts_with('='))
+        .map(|l| l.trim_start().trim_start_matches('=').trim())
+        .next()
+        .unwrap_or(&cause)
+        .to_string();
+    match line {
+        Some(line) => anyhow::anyhow!("{}:{}: {}", file.display(), line, message),
+        None => anyhow::anyhow!("{}: {}", file.display(), message),
+    }
 }
 
 #[cfg(test)]
@@ -468,4 +841,292 @@ mod tests {
                 .join("\n")
         );
     }
+
+    fn swap(key: &str, val: &str) -> Swap {
+        Swap {
+            key: key.to_string(),
+            val: Some(val.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn render_with(swaps: &[Swap], text: &str) -> (String, usize) {
+        let swapper = Swapper::with_vars(Some(&swaps.to_vec()), &BTreeMap::new()).unwrap();
+        let (out, count) = swapper.render_content(Path::new("file.txt"), text);
+        (out.into_owned(), count)
+    }
+
+    #[test]
+    fn test_overlapping_keys_longest_wins() {
+        let text = "foobar foo barfoo";
+        let expected = ("LONG SHORT barSHORT".to_string(), 3);
+        assert_eq!(
+            render_with(&[swap("foo", "SHORT"), swap("foobar", "LONG")], text),
+            expected
+        );
+        assert_eq!(
+            render_with(&[swap("foobar", "LONG"), swap("foo", "SHORT")], text),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_overlapping_keys_leftmost_wins() {
+        // "abc" starts first, so "bcd" never gets its chance
+        let text = "abcd";
+        assert_eq!(
+            render_with(&[swap("bcd", "2"), swap("abc", "1")], text),
+            ("1d".to_string(), 1)
+        );
+        assert_eq!(
+            render_with(&[swap("abc", "1"), swap("bcd", "2")], text),
+            ("1d".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_keys_of_other_swaps_do_not_shadow() {
+        // "foobar" only applies to paths, so in content "foo" is the one to match
+        let swaps = vec![
+            Swap {
+                kind: SwapKind::Path,
+                ..swap("foobar", "LONG")
+            },
+            swap("foo", "SHORT"),
+        ];
+        assert_eq!(render_with(&swaps, "foobar"), ("SHORTbar".to_string(), 1));
+    }
+
+    #[test]
+    fn test_swaps_do_not_cascade() {
+        let text = "A B C";
+        let expected = ("B C A".to_string(), 3);
+        let mut swaps = vec![swap("A", "B"), swap("B", "C"), swap("C", "A")];
+        assert_eq!(render_with(&swaps, text), expected);
+        swaps.reverse();
+        assert_eq!(render_with(&swaps, text), expected);
+    }
+
+    #[test]
+    fn test_literal_and_regex_keys_overlap() {
+        let regex = Swap {
+            regex: true,
+            ..swap(r"v(\d+)\.(\d+)", "v$1.x")
+        };
+        let text = "v1.2 v1.2.3-beta";
+        // the literal key is longer where both match
+        assert_eq!(
+            render_with(&[regex.clone(), swap("v1.2.3-beta", "next")], text),
+            ("v1.x next".to_string(), 2)
+        );
+        assert_eq!(
+            render_with(&[swap("v1.2.3-beta", "next"), regex], text),
+            ("v1.x next".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn test_unmatched_swaps() {
+        let swaps = vec![swap("HIT", "x"), swap("MISS", "y"), swap("IN_PATH", "z")];
+        let swapper = Swapper::with_vars(Some(&swaps), &BTreeMap::new()).unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let source = out.path().join("file.txt");
+        fs::write(&source, "a HIT here").unwrap();
+        let dest = out.path().join("src/IN_PATH/file");
+
+        // only what's written counts
+        assert_eq!(
+            swapper.matches(&source, &dest).unwrap(),
+            BTreeSet::from([0, 2])
+        );
+        swapper.plan(&source, &dest).unwrap();
+        swapper.content(&source).unwrap();
+        assert_eq!(swapper.unmatched().len(), 3);
+
+        swapper.copy_to(&source, &dest).unwrap();
+        assert_eq!(
+            swapper
+                .unmatched()
+                .iter()
+                .map(|s| s.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["MISS"]
+        );
+    }
+
+    #[test]
+    fn test_regex_swaps() {
+        let swaps = vec![
+            Swap {
+                key: r#"name = "old-([a-z]+)""#.to_string(),
+                val_template: Some(r#"name = "{{prefix}}-$1""#.to_string()),
+                regex: true,
+                ..Default::default()
+            },
+            Swap {
+                key: r"v(?P<major>\d+)".to_string(),
+                val: Some("version-${major}".to_string()),
+                kind: SwapKind::Path,
+                regex: true,
+                ..Default::default()
+            },
+        ];
+        let vars = BTreeMap::from([("prefix".to_string(), "new".to_string())]);
+        let swapper = Swapper::with_vars(Some(&swaps), &vars).unwrap();
+
+        let (out, count) = swapper.render_content(
+            Path::new("Cargo.toml"),
+            "name = \"old-core\"\nname = \"old-cli\"\nname = \"other\"\n",
+        );
+        assert_eq!(
+            out,
+            "name = \"new-core\"\nname = \"new-cli\"\nname = \"other\"\n"
+        );
+        assert_eq!(count, 2);
+
+        assert_eq!(
+            swapper.path(Path::new("docs/v2/readme.md")),
+            PathBuf::from("docs/version-2/readme.md")
+        );
+    }
+
+    #[test]
+    fn test_regex_swap_bad_key() {
+        let swaps = vec![Swap {
+            key: "(unclosed".to_string(),
+            val: Some("x".to_string()),
+            regex: true,
+            ..Default::default()
+        }];
+        assert_eq!(
+            Swapper::with_vars(Some(&swaps), &BTreeMap::new())
+                .err()
+                .unwrap()
+                .to_string(),
+            "bad regex in swap key '(unclosed'"
+        );
+    }
+
+    fn template_swapper(from: &Path, to: &Path) -> Swapper {
+        let swaps = vec![Swap {
+            key: "AUTHOR".to_string(),
+            val: Some("me".to_string()),
+            ..Default::default()
+        }];
+        let vars = BTreeMap::from([("name".to_string(), "crewl world".to_string())]);
+        Swapper::with_vars(Some(&swaps), &vars)
+            .unwrap()
+            .with_templates(&["src/**/*.tera".to_string()], from, to)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_templates() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        fs::create_dir_all(from.path().join("src")).unwrap();
+        fs::write(
+            from.path().join("src/main.rs.tera"),
+            "{% if name %}// {{ name | kebab_case }} by AUTHOR{% endif %}\n{% for i in [1, 2] %}{{ i }}{% endfor %}\n",
+        )
+        .unwrap();
+        fs::write(from.path().join("notes.tera"), "{{ name }}").unwrap();
+        let swapper = template_swapper(from.path(), to.path());
+
+        let res = swapper
+            .copy_to(
+                &from.path().join("src/main.rs.tera"),
+                &to.path().join("src/main.rs.tera"),
+            )
+            .unwrap();
+        assert_eq!(res.dest, to.path().join("src/main.rs"));
+        assert_eq!(
+            fs::read_to_string(&res.dest).unwrap(),
+            "// crewl-world by me\n12\n"
+        );
+
+        // not matching the patterns, copied as is
+        let res = swapper
+            .copy_to(
+                &from.path().join("notes.tera"),
+                &to.path().join("notes.tera"),
+            )
+            .unwrap();
+        assert_eq!(res.dest, to.path().join("notes.tera"));
+        assert_eq!(fs::read_to_string(&res.dest).unwrap(), "{{ name }}");
+    }
+
+    #[test]
+    fn test_render_template_errors() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        fs::create_dir_all(from.path().join("src")).unwrap();
+        let file = from.path().join("src/lib.rs.tera");
+        let swapper = template_swapper(from.path(), to.path());
+
+        fs::write(&file, "one\ntwo\n{{ oops \n").unwrap();
+        let err = swapper.content(&file).unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("{}:4: expected", file.display())),
+            "{err}"
+        );
+
+        fs::write(&file, "one\n{{ name }}\n{{ missing }}\n").unwrap();
+        let err = swapper.content(&file).unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!(
+                "{}:3: Variable `missing` not found",
+                file.display()
+            )),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_eval_condition() {
+        let vars = BTreeMap::from([
+            ("use_docker".to_string(), "yes".to_string()),
+            ("ci".to_string(), "false".to_string()),
+        ]);
+        assert!(eval_condition("{{ use_docker == 'yes' }}", &vars).unwrap());
+        assert!(eval_condition("use_docker == 'yes'", &vars).unwrap());
+        assert!(!eval_condition("use_docker == 'no'", &vars).unwrap());
+        assert!(!eval_condition("{{ ci }}", &vars).unwrap());
+        assert!(!eval_condition("{% if ci == 'true' %}yes{% endif %}", &vars).unwrap());
+        assert!(eval_condition("nope is not defined", &vars).unwrap());
+
+        let err = eval_condition("nope == 'yes'", &vars).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "condition 'nope == 'yes'': Variable `nope` not found in context"
+        );
+    }
+
+    #[test]
+    fn test_plan_does_not_write() {
+        let swaps = vec![
+            Swap {
+                key: "better".to_string(),
+                kind: SwapKind::All,
+                val: Some("worse".to_string()),
+                ..Default::default()
+            },
+            Swap {
+                key: "SWAP_ME".to_string(),
+                kind: SwapKind::Path,
+                val: Some("planned".to_string()),
+                ..Default::default()
+            },
+        ];
+        let swapper = Swapper::with_vars(Some(&swaps), &BTreeMap::new()).unwrap();
+        let res = swapper
+            .plan(
+                Path::new("tests/fixtures/content/SWAP_ME/hey.txt"),
+                Path::new("tests-out/plan/SWAP_ME/hey.txt"),
+            )
+            .unwrap();
+        assert_eq!(res.dest, PathBuf::from("tests-out/plan/planned/hey.txt"));
+        assert!(matches!(res.op, SwapOp::Rendered(n) if n > 0));
+        assert!(!Path::new("tests-out/plan").exists());
+    }
 }
@@ -0,0 +1,166 @@
+//! Trust-on-first-use for the `run` actions a template ships in its own
+//! `.backpack-project.yaml`: they're shell commands written by whoever owns the
+//! repo, so they only run once the user trusted that repo at that revision, with
+//! those exact commands.
+//!
+//! Trust is kept in a file under the global folder.
+use crate::config::{ProjectAction, ProjectSetupActions};
+use crate::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const TRUST_FILE: &str = "trusted.yaml";
+
+/// Fingerprints the user trusted, by repository
+pub struct TrustStore {
+    path: PathBuf,
+    repos: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl TrustStore {
+    /// Load the trust kept in `folder`, if any was kept yet
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the trust file exists but cannot be read
+    pub fn load(folder: &Path) -> Result<Self> {
+        let path = folder.join(TRUST_FILE);
+        let repos = if path.is_file() {
+            serde_yaml::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| Error::from(e).in_file(&path))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { path, repos })
+    }
+
+    pub fn is_trusted(&self, repo: &str, fingerprint: &str) -> bool {
+        self.repos
+            .get(repo)
+            .is_some_and(|fingerprints| fingerprints.contains(fingerprint))
+    }
+
+    /// Trust `repo` with `fingerprint`, and keep it for the next runs
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the trust file cannot be written
+    pub fn trust(&mut self, repo: &str, fingerprint: &str) -> Result<()> {
+        self.repos
+            .entry(repo.to_string())
+            .or_default()
+            .insert(fingerprint.to_string());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_yaml::to_string(&self.repos)?)?;
+        Ok(())
+    }
+}
+
+/// The commands a setup would run, as written
+pub fn commands(setup: &ProjectSetupActions) -> Vec<String> {
+    setup
+        .actions
+        .iter()
+        .flatten()
+        .filter_map(|a| a.action.run.clone())
+        .collect()
+}
+
+/// What trust is given to: the exact commands, at the resolved revision when a
+/// vendor resolves one. Changing a command asks again, whatever the revision.
+pub fn fingerprint(revision: Option<&str>, commands: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for command in commands {
+        // length-prefixed, so that no two lists of commands hash the same
+        hasher.update(command.len().to_le_bytes());
+        hasher.update(command);
+    }
+    let hash = format!("sha256:{:x}", hasher.finalize());
+    revision.map_or_else(|| hash.clone(), |revision| format!("{revision}@{hash}"))
+}
+
+/// The setup without its commands. Interactions stay, so swaps still get their
+/// answers; actions that only ran a command are gone.
+pub fn without_commands(setup: ProjectSetupActions) -> ProjectSetupActions {
+    let actions = setup.actions.map(|actions| {
+        actions
+            .into_iter()
+            .filter_map(|mut a: ProjectAction| {
+                a.action.run = None;
+                a.action.interaction.is_some().then_some(a)
+            })
+            .collect()
+    });
+    ProjectSetupActions { actions, ..setup }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LocalProjectConfig;
+    use pretty_assertions::assert_eq;
+
+    fn setup() -> ProjectSetupActions {
+        let local = LocalProjectConfig::from_text(
+            r###"
+project:
+  shortlink: template
+  actions:
+    - name: name
+      interaction:
+        kind: input
+        prompt: name?
+        out: name
+      run: echo {{name}}
+    - name: install
+      run: curl example.com | sh
+"###,
+        )
+        .unwrap();
+        ProjectSetupActions::from(&local.project)
+    }
+
+    #[test]
+    fn test_trust_store() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut store = TrustStore::load(folder.path()).unwrap();
+        assert!(!store.is_trusted("github.com/org/repo", "abc"));
+
+        store.trust("github.com/org/repo", "abc").unwrap();
+        let store = TrustStore::load(folder.path()).unwrap();
+        assert!(store.is_trusted("github.com/org/repo", "abc"));
+        assert!(!store.is_trusted("github.com/org/repo", "def"));
+        assert!(!store.is_trusted("github.com/org/other", "abc"));
+    }
+
+    #[test]
+    fn test_commands() {
+        let setup = setup();
+        let commands = commands(&setup);
+        assert_eq!(commands, vec!["echo {{name}}", "curl example.com | sh"]);
+
+        assert!(fingerprint(None, &commands).starts_with("sha256:"));
+        assert_eq!(
+            fingerprint(Some("abc"), &commands),
+            format!("abc@{}", fingerprint(None, &commands))
+        );
+        assert_ne!(
+            fingerprint(Some("abc"), &commands),
+            fingerprint(Some("abc"), &commands[..1])
+        );
+        assert_ne!(
+            fingerprint(None, &["a\nb".to_string()]),
+            fingerprint(None, &["a".to_string(), "b".to_string()])
+        );
+
+        let actions = without_commands(setup).actions.unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].action.name, "name");
+        assert!(actions[0].action.run.is_none());
+        assert!(actions[0].action.interaction.is_some());
+    }
+}
@@ -1,17 +1,97 @@
 use crate::config::Config;
+use crate::content::{FilePlan, Plan};
 use crate::data::CopyMode;
+use crate::merge::MergeOutcome;
 use crate::run::RunnerEvents;
 use crate::templates::CopyResult;
-use anyhow::{anyhow, Context, Result as AnyResult};
+use crate::Error;
+use anyhow::{anyhow, Result as AnyResult};
 use console::style;
-use interactive_actions::data::ActionResult;
+use diffy::Line;
+use interactive_actions::data::{ActionHook, ActionResult};
 use requestty::{Answer, Answers, OnEsc, Question};
-use requestty_ui::backend::{Size, TestBackend};
-use requestty_ui::events::{KeyEvent, TestEvents};
+use requestty_ui::backend::{Backend, Size, TestBackend};
+use requestty_ui::events::{EventIterator, KeyEvent, TestEvents};
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::vec::IntoIter;
 
+/// An answer to overwriting an existing file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwriteChoice {
+    Overwrite,
+    Skip,
+    /// overwrite this one, and every other existing file without asking
+    OverwriteAll,
+    /// skip this one, and every other existing file without asking
+    SkipAll,
+}
+
+/// Everything the generation workflow needs to ask the user, or tell them.
+/// [`Prompt`] implements it for the terminal; library users can bring their own.
+pub trait Prompter {
+    /// Fill in missing arguments with a wizard. Returns the shortlink, the destination
+    /// and whether the user should confirm before generating.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if prompting fails
+    fn fill_missing(
+        &mut self,
+        shortlink: Option<&str>,
+        dest: Option<&str>,
+        mode: &CopyMode,
+    ) -> AnyResult<(String, Option<String>, bool)>;
+
+    /// Ask a yes/no question.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if interaction is killed
+    fn are_you_sure(&mut self, text: &str) -> AnyResult<bool>;
+
+    /// Confirm file overwrite. `new_content` is what would be written, or `None`
+    /// if it's binary.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if prompting does not work
+    fn confirm_overwrite(
+        &mut self,
+        file: &Path,
+        new_content: Option<&str>,
+    ) -> AnyResult<OverwriteChoice>;
+
+    /// Ask whether to trust `template` with running `commands` on this machine
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if prompting fails
+    fn confirm_trust(&mut self, template: &str, commands: &[String]) -> AnyResult<bool> {
+        self.are_you_sure(&format!(
+            "'{}' wants to run:\n{}\nTrust it?",
+            template,
+            commands.join("\n")
+        ))
+    }
+
+    fn say_resolving(&self);
+    fn say_fetching(&self);
+    fn say_unpacking(&self);
+    fn say_action(&self, name: &str);
+    /// Sum up what was generated: files, actions, and swaps that didn't match anything
+    fn say_done(
+        &self,
+        res: &[CopyResult],
+        maybe_actions: Option<&Vec<ActionResult>>,
+        unmatched_swaps: &[String],
+    );
+
+    /// Show what a dry run would generate
+    fn say_plan(&self, plan: &Plan);
+}
+
 pub struct Prompt<'a> {
     config: &'a Config,
     events: Option<TestEvents<IntoIter<KeyEvent>>>,
@@ -44,46 +124,6 @@ impl<'a> Prompt<'a> {
         }
     }
 
-    /// Fill in missing arguments with a wizard
-    ///
-    /// # Errors
-    ///
-    /// This function will return an error if prompting fails
-    pub fn fill_missing(
-        &mut self,
-        shortlink: Option<&str>,
-        dest: Option<&str>,
-        mode: &CopyMode,
-    ) -> AnyResult<(String, Option<String>, bool)> {
-        match (shortlink, dest) {
-            (Some(s), Some(d)) => Ok((s.to_string(), Some(d.to_string()), false)),
-            (None, d) => {
-                let shortlink = if let Some(project) = self.pick_project()? {
-                    project
-                } else {
-                    self.input_shortlink()?
-                };
-                if let Some(d) = d {
-                    Ok((shortlink, Some(d.to_string()), true))
-                } else {
-                    Ok((shortlink, self.input_dest()?, true))
-                }
-            }
-            (Some(s), None) => Ok((
-                s.to_string(),
-                // when applying, if no target folder, it's always the current one so let it resolve
-                // automatically in a smart way later down the road by *not* filling in
-                // if it's copy (clone new project), ask the user where to
-                if *mode == CopyMode::Copy {
-                    self.input_dest()?
-                } else {
-                    None
-                },
-                true,
-            )),
-        }
-    }
-
     /// Returns the pick project of this [`Prompt`].
     ///
     /// # Errors
@@ -110,7 +150,7 @@ impl<'a> Prompt<'a> {
                     .choices(options)
                     .build();
 
-                let selection = requestty::prompt(vec![question])?
+                let selection = prompt_terminal(vec![question])?
                     .get("question")
                     .and_then(|a| a.as_list_item().cloned());
 
@@ -213,62 +253,289 @@ impl<'a> Prompt<'a> {
         }
     }
 
-    /// Returns the input shortlink of this [`Prompt`].
+    pub fn say_actions(&self, number: usize) {
+        if self.show_progress {
+            println!("🍿 Running {number} action(s):");
+        }
+    }
+
+    pub fn say(&self, text: &str) {
+        println!("{text}");
+    }
+
+    /// Ask if user wants to edit a file and open editor
     ///
     /// # Errors
     ///
-    /// This function will return an error if interaction is killed
-    pub fn are_you_sure(&mut self, text: &str) -> AnyResult<bool> {
-        let question = Question::confirm("question")
-            .message(text)
-            .default(true)
-            .build();
+    /// This function will return an error if IO failed
+    pub fn suggest_edit(&mut self, text: &str, path: &Path) -> AnyResult<()> {
+        if self.are_you_sure(text)? {
+            edit::edit_file(path)?;
+        }
+        Ok(())
+    }
 
-        Ok(self.prompt_one(question)?.as_bool().unwrap_or(false))
+    fn prompt_one<I: Into<Question<'a>>>(&mut self, question: I) -> AnyResult<Answer> {
+        match self.events {
+            Some(ref mut events) => {
+                let mut backend = TestBackend::new(Size::from((50, 20)));
+                requestty::prompt_one_with(question, &mut backend, events).map_err(input_error)
+            }
+            None => {
+                let (mut backend, mut events) = terminal();
+                requestty::prompt_one_with(question, &mut backend, &mut events).map_err(input_error)
+            }
+        }
     }
 
-    /// Confirm file overwrite
+    /// Prompt questions
     ///
     /// # Errors
     ///
-    /// This function will return an error if prompting does not work
-    pub fn confirm_overwrite(&mut self, file: &Path) -> AnyResult<bool> {
+    /// This function will return an error if prompting fails
+    pub fn prompt<Q>(&mut self, questions: Q) -> AnyResult<Answers>
+    where
+        Q: IntoIterator<Item = Question<'a>>,
+    {
+        match self.events {
+            Some(ref mut events) => {
+                let mut backend = TestBackend::new(Size::from((50, 20)));
+                requestty::prompt_with(questions, &mut backend, events).map_err(input_error)
+            }
+            None => prompt_terminal(questions),
+        }
+    }
+}
+
+/// A [`Prompter`] for pipelines: it never touches the terminal's input, whatever
+/// would ask fails with [`Error::Headless`]. Progress is still reported.
+pub struct Headless<'a> {
+    prompt: Prompt<'a>,
+}
+
+impl<'a> Headless<'a> {
+    pub fn new(config: &'a Config, show_progress: bool) -> Self {
+        Self {
+            prompt: Prompt::new(config, show_progress),
+        }
+    }
+}
+
+impl Prompter for Headless<'_> {
+    fn fill_missing(
+        &mut self,
+        shortlink: Option<&str>,
+        dest: Option<&str>,
+        mode: &CopyMode,
+    ) -> AnyResult<(String, Option<String>, bool)> {
+        let shortlink = shortlink.ok_or_else(|| Error::Headless("shortlink".to_string()))?;
+        // applying defaults to the current folder, a new project has to be told where to go
+        if dest.is_none() && *mode == CopyMode::Copy {
+            return Err(Error::Headless("destination".to_string()).into());
+        }
+        Ok((shortlink.to_string(), dest.map(ToString::to_string), false))
+    }
+
+    fn are_you_sure(&mut self, text: &str) -> AnyResult<bool> {
+        Err(Error::Headless(text.to_string()).into())
+    }
+
+    fn confirm_overwrite(
+        &mut self,
+        file: &Path,
+        _new_content: Option<&str>,
+    ) -> AnyResult<OverwriteChoice> {
+        Err(Error::Headless(format!(
+            "'{}' already exists, use --on-conflict",
+            file.display()
+        ))
+        .into())
+    }
+
+    fn say_resolving(&self) {
+        self.prompt.say_resolving();
+    }
+
+    fn say_fetching(&self) {
+        self.prompt.say_fetching();
+    }
+
+    fn say_unpacking(&self) {
+        self.prompt.say_unpacking();
+    }
+
+    fn say_action(&self, name: &str) {
+        self.prompt.say_action(name);
+    }
+
+    fn say_done(
+        &self,
+        res: &[CopyResult],
+        maybe_actions: Option<&Vec<ActionResult>>,
+        unmatched_swaps: &[String],
+    ) {
+        self.prompt.say_done(res, maybe_actions, unmatched_swaps);
+    }
+
+    fn say_plan(&self, plan: &Plan) {
+        self.prompt.say_plan(plan);
+    }
+}
+
+/// The terminal to ask on. Questions are drawn on stderr, so that stdout stays
+/// clean for `--json`.
+fn terminal() -> (impl Backend, impl EventIterator) {
+    (
+        requestty_ui::backend::get_backend(std::io::stderr()),
+        requestty_ui::events::get_events(),
+    )
+}
+
+fn prompt_terminal<'a, Q>(questions: Q) -> AnyResult<Answers>
+where
+    Q: IntoIterator<Item = Question<'a>>,
+{
+    let (mut backend, mut events) = terminal();
+    requestty::prompt_with(questions, &mut backend, &mut events).map_err(input_error)
+}
+
+/// Ctrl+C, Esc and EOF all mean the user walked away from the prompt
+fn input_error(err: requestty::ErrorKind) -> anyhow::Error {
+    match err {
+        requestty::ErrorKind::Interrupted
+        | requestty::ErrorKind::Aborted
+        | requestty::ErrorKind::Eof => Error::Cancelled.into(),
+        requestty::ErrorKind::IoError(err) => anyhow::Error::new(err).context("cannot take input"),
+    }
+}
+
+impl<'a> Prompter for Prompt<'a> {
+    fn fill_missing(
+        &mut self,
+        shortlink: Option<&str>,
+        dest: Option<&str>,
+        mode: &CopyMode,
+    ) -> AnyResult<(String, Option<String>, bool)> {
+        match (shortlink, dest) {
+            (Some(s), Some(d)) => Ok((s.to_string(), Some(d.to_string()), false)),
+            (None, d) => {
+                let shortlink = if let Some(project) = self.pick_project()? {
+                    project
+                } else {
+                    self.input_shortlink()?
+                };
+                if let Some(d) = d {
+                    Ok((shortlink, Some(d.to_string()), true))
+                } else {
+                    Ok((shortlink, self.input_dest()?, true))
+                }
+            }
+            (Some(s), None) => Ok((
+                s.to_string(),
+                // when applying, if no target folder, it's always the current one so let it resolve
+                // automatically in a smart way later down the road by *not* filling in
+                // if it's copy (clone new project), ask the user where to
+                if *mode == CopyMode::Copy {
+                    self.input_dest()?
+                } else {
+                    None
+                },
+                true,
+            )),
+        }
+    }
+
+    fn are_you_sure(&mut self, text: &str) -> AnyResult<bool> {
         let question = Question::confirm("question")
-            .message(format!("'{}' already exists. overwrite?", file.display()))
+            .message(text)
+            .default(true)
             .build();
 
         Ok(self.prompt_one(question)?.as_bool().unwrap_or(false))
     }
 
-    pub fn say_resolving(&self) {
+    fn confirm_overwrite(
+        &mut self,
+        file: &Path,
+        new_content: Option<&str>,
+    ) -> AnyResult<OverwriteChoice> {
+        loop {
+            let question = Question::expand("question")
+                .message(format!("'{}' already exists. overwrite?", file.display()))
+                .choices(vec![
+                    ('y', "Overwrite"),
+                    ('n', "Skip"),
+                    ('a', "Overwrite all"),
+                    ('s', "Skip all"),
+                    ('d', "Show diff"),
+                ])
+                .default('n')
+                .build();
+
+            let key = self
+                .prompt_one(question)?
+                .as_expand_item()
+                .map(|item| item.key);
+            match key {
+                Some('y') => return Ok(OverwriteChoice::Overwrite),
+                Some('a') => return Ok(OverwriteChoice::OverwriteAll),
+                Some('s') => return Ok(OverwriteChoice::SkipAll),
+                Some('d') => {
+                    let existing = fs::read(file)?;
+                    match (std::str::from_utf8(&existing), new_content) {
+                        (Ok(existing), Some(new_content)) => {
+                            eprint!("{}", colored_diff(existing, new_content, file));
+                        }
+                        _ => eprintln!("binary files differ"),
+                    }
+                }
+                _ => return Ok(OverwriteChoice::Skip),
+            }
+        }
+    }
+
+    fn confirm_trust(&mut self, template: &str, commands: &[String]) -> AnyResult<bool> {
+        // asked even with `--json`, so it goes with the question to stderr
+        eprintln!(
+            "🔐 '{}' comes with commands to run:",
+            style(template).yellow()
+        );
+        for command in commands {
+            eprintln!("   {}", style(command).cyan());
+        }
+        self.are_you_sure("Trust it to run them, at this revision?")
+    }
+
+    fn say_resolving(&self) {
         if self.show_progress {
             println!("🔮 Resolving...");
         }
     }
 
-    pub fn say_fetching(&self) {
+    fn say_fetching(&self) {
         if self.show_progress {
             println!("🚚 Fetching content...");
         }
     }
 
-    pub fn say_unpacking(&self) {
+    fn say_unpacking(&self) {
         if self.show_progress {
             println!("🎒 Unpacking files...");
         }
     }
 
-    pub fn say_action(&self, name: &str) {
-        println!("{name}");
-    }
-
-    pub fn say_actions(&self, number: usize) {
+    fn say_action(&self, name: &str) {
         if self.show_progress {
-            println!("🍿 Running {number} action(s):");
+            println!("{name}");
         }
     }
 
-    pub fn say_done(&self, res: &[CopyResult], maybe_actions: Option<&Vec<ActionResult>>) {
+    fn say_done(
+        &self,
+        res: &[CopyResult],
+        maybe_actions: Option<&Vec<ActionResult>>,
+        unmatched_swaps: &[String],
+    ) {
         if self.show_progress {
             let total = res.len();
             let total_actions = maybe_actions.map_or(0, Vec::len);
@@ -306,53 +573,116 @@ impl<'a> Prompt<'a> {
                 style(total).yellow(),
                 style(total_actions).yellow()
             );
-        }
-    }
 
-    pub fn say(&self, text: &str) {
-        println!("{text}");
-    }
+            // conflicts are never cut off, each one needs the user's attention
+            let conflicts = res
+                .iter()
+                .filter(|r| matches!(r.merge, Some(MergeOutcome::Conflict | MergeOutcome::Binary)))
+                .collect::<Vec<_>>();
+            if !conflicts.is_empty() {
+                println!(
+                    "\n⚠️  {} file(s) could not be merged cleanly:",
+                    style(conflicts.len()).red()
+                );
+                for r in conflicts {
+                    println!(
+                        " {} {} {}",
+                        style("!").red(),
+                        style(r.dest.display()),
+                        style(if r.merge == Some(MergeOutcome::Binary) {
+                            "binary, kept yours"
+                        } else {
+                            "resolve the conflict markers"
+                        })
+                        .dim()
+                    );
+                }
+            }
 
-    /// Ask if user wants to edit a file and open editor
-    ///
-    /// # Errors
-    ///
-    /// This function will return an error if IO failed
-    pub fn suggest_edit(&mut self, text: &str, path: &Path) -> AnyResult<()> {
-        if self.are_you_sure(text)? {
-            edit::edit_file(path)?;
+            say_unmatched(unmatched_swaps);
         }
-        Ok(())
     }
 
-    fn prompt_one<I: Into<Question<'a>>>(&mut self, question: I) -> AnyResult<Answer> {
-        match self.events {
-            Some(ref mut events) => {
-                let mut backend = TestBackend::new(Size::from((50, 20)));
-                requestty::prompt_one_with(question, &mut backend, events)
-                    .context("cannot take input")
-            }
-            None => requestty::prompt_one(question).context("cannot take input"),
+    fn say_plan(&self, plan: &Plan) {
+        if !self.show_progress {
+            return;
+        }
+        println!();
+        for action in plan.actions.iter().filter(|a| a.hook == ActionHook::Before) {
+            println!(" {} {}", style("▶").cyan(), style(&action.name).dim());
+        }
+        for file in &plan.files {
+            let (mark, what) = match file.plan {
+                FilePlan::Create => (style("+").green(), "create"),
+                FilePlan::Overwrite => (style("~").yellow(), "overwrite"),
+                FilePlan::Ask => (style("?").yellow(), "ask to overwrite"),
+                FilePlan::Merge => (style("~").yellow(), "merge"),
+                FilePlan::Backup => (style("~").yellow(), "back up and overwrite"),
+                FilePlan::Conflict => (style("!").red(), "conflict"),
+                FilePlan::Skip => (style("-").dim(), "skip"),
+            };
+            println!(
+                " {} {} {}",
+                mark,
+                style(file.dest.display()).dim(),
+                style(match file.swaps {
+                    Some(count) => format!("{what}, {count} swap(s)"),
+                    None => what.to_string(),
+                })
+                .yellow()
+                .dim()
+            );
         }
+        for action in plan.actions.iter().filter(|a| a.hook == ActionHook::After) {
+            println!(" {} {}", style("▶").cyan(), style(&action.name).dim());
+        }
+        println!(
+            "\n🔍 Dry run: {} file(s) and {} action(s) planned, nothing was written.",
+            style(plan.files.len()).yellow(),
+            style(plan.actions.len()).yellow()
+        );
+        say_unmatched(&plan.unmatched_swaps);
     }
+}
 
-    /// Prompt questions
-    ///
-    /// # Errors
-    ///
-    /// This function will return an error if prompting fails
-    pub fn prompt<Q>(&mut self, questions: Q) -> AnyResult<Answers>
-    where
-        Q: IntoIterator<Item = Question<'a>>,
-    {
-        match self.events {
-            Some(ref mut events) => {
-                let mut backend = TestBackend::new(Size::from((50, 20)));
-                requestty::prompt_with(questions, &mut backend, events).context("cannot take input")
+fn say_unmatched(unmatched_swaps: &[String]) {
+    if unmatched_swaps.is_empty() {
+        return;
+    }
+    println!(
+        "\n⚠️  {} swap(s) did not match anything, has the template changed?",
+        style(unmatched_swaps.len()).yellow()
+    );
+    for key in unmatched_swaps {
+        println!(" {} {}", style("?").yellow(), style(key).dim());
+    }
+}
+
+/// A unified diff of `old` to `new`, colored for the terminal
+pub fn colored_diff(old: &str, new: &str, file: &Path) -> String {
+    let patch = diffy::create_patch(old, new);
+    let name = file.display().to_string();
+    let mut out = format!(
+        "{}\n{}\n",
+        style(format!("--- {name}")).red(),
+        style(format!("+++ {name}")).green()
+    );
+    for hunk in patch.hunks() {
+        let header = format!("@@ -{} +{} @@", hunk.old_range(), hunk.new_range());
+        out.push_str(&format!("{}\n", style(header).cyan()));
+        for line in hunk.lines() {
+            let (text, line) = match *line {
+                Line::Delete(text) => (text, style(format!("-{text}")).red()),
+                Line::Insert(text) => (text, style(format!("+{text}")).green()),
+                Line::Context(text) => (text, style(format!(" {text}")).dim()),
+            };
+            out.push_str(&line.to_string());
+            if !text.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
             }
-            None => requestty::prompt(questions).context("cannot take input"),
         }
     }
+    out
 }
 
 /// Guess a destination name by generating a "my-projectN..99" for the user.
@@ -368,3 +698,18 @@ pub fn guess_dest() -> AnyResult<String> {
         .display()
         .to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colored_diff() {
+        console::set_colors_enabled(false);
+        let diff = colored_diff("a\nb\nc\n", "a\nB\nc\nd", Path::new("file.txt"));
+        assert_eq!(
+            diff,
+            "--- file.txt\n+++ file.txt\n@@ -1,3 +1,4 @@\n a\n-b\n+B\n c\n+d\n\\ No newline at end of file\n"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Variables available to every `val_template`, rendered file and action: the
+//! ones backpack knows before asking anything, and answers given up front.
+use crate::data::Location;
+use crate::git::RemoteInfo;
+use crate::{Error, Result};
+use chrono::Local;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Built-in variable names all start with this
+pub const BUILTIN_PREFIX: &str = "bp_";
+
+/// Is `key` a built-in variable, rather than an answer?
+pub fn is_builtin(key: &str) -> bool {
+    key.starts_with(BUILTIN_PREFIX)
+}
+
+/// Parse a `key=value` assignment, as given with `--var`
+///
+/// # Errors
+///
+/// This function will return an error if there's no `=` or no key
+pub fn parse_assignment(text: &str) -> Result<(String, String)> {
+    match text.split_once('=') {
+        Some((key, val)) if !key.trim().is_empty() => Ok((key.trim().to_string(), val.to_string())),
+        _ => Err(Error::config(format!(
+            "'{text}' is not a variable assignment, expected key=value"
+        ))),
+    }
+}
+
+/// Load answers from a YAML (or JSON) file mapping variable names to values
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be read, or isn't a map of plain values
+pub fn load_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    let text = fs::read_to_string(path)?;
+    let map: BTreeMap<String, Value> =
+        serde_yaml::from_str(&text).map_err(|e| Error::from(e).in_file(path))?;
+    map.into_iter()
+        .map(|(key, val)| {
+            let val = match val {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                Value::Null => String::new(),
+                _ => {
+                    return Err(
+                        Error::config(format!("'{key}' should be a plain value")).in_file(path)
+                    )
+                }
+            };
+            Ok((key, val))
+        })
+        .collect()
+}
+
+/// What's known about a generation before it starts
+pub struct Builtins<'a> {
+    pub shortlink: &'a str,
+    pub vendor: &'a str,
+    pub location: &'a Location,
+    pub remote: Option<&'a RemoteInfo>,
+    pub dest: &'a Path,
+    /// `user.name` and `user.email` from the user's git configuration
+    pub git_user_name: Option<String>,
+    pub git_user_email: Option<String>,
+}
+
+impl Builtins<'_> {
+    /// The built-in variables. Unknown values are empty, so templates using
+    /// them still render.
+    pub fn vars(&self) -> BTreeMap<String, String> {
+        let now = Local::now();
+        [
+            ("dest_name", dest_name(self.dest)),
+            ("shortlink", self.shortlink.to_string()),
+            ("vendor", self.vendor.to_string()),
+            ("owner", self.owner()),
+            ("repo_name", self.location.project.clone()),
+            ("ref", self.gref()),
+            (
+                "revision",
+                self.remote.map(|r| r.revision.clone()).unwrap_or_default(),
+            ),
+            ("date", now.format("%Y-%m-%d").to_string()),
+            ("year", now.format("%Y").to_string()),
+            (
+                "git_user_name",
+                self.git_user_name.clone().unwrap_or_default(),
+            ),
+            (
+                "git_user_email",
+                self.git_user_email.clone().unwrap_or_default(),
+            ),
+        ]
+        .into_iter()
+        .map(|(k, v)| (format!("{BUILTIN_PREFIX}{k}"), v))
+        .collect()
+    }
+
+    /// The user or group(s) owning the repo: the path without the repo's name
+    fn owner(&self) -> String {
+        if self.vendor == "local" {
+            return String::new();
+        }
+        self.location
+            .path
+            .trim_matches('/')
+            .rsplit_once('/')
+            .map(|(owner, _)| owner.to_string())
+            .unwrap_or_default()
+    }
+
+    /// A short branch or tag name
+    fn gref(&self) -> String {
+        self.remote
+            .map(|r| {
+                r.ref_
+                    .trim_start_matches("refs/heads/")
+                    .trim_start_matches("refs/tags/")
+                    .to_string()
+            })
+            .or_else(|| self.location.gref.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// The name of the folder generated into, or of the folder a single file goes into
+fn dest_name(dest: &Path) -> String {
+    std::path::absolute(dest)
+        .ok()
+        .and_then(|abs| {
+            // `.` components are dropped, but `..` would name the wrong folder
+            let mut normal = std::path::PathBuf::new();
+            for part in abs.components() {
+                match part {
+                    std::path::Component::ParentDir => {
+                        normal.pop();
+                    }
+                    std::path::Component::CurDir => {}
+                    other => normal.push(other),
+                }
+            }
+            normal
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use url::Url;
+
+    #[test]
+    fn test_builtin_vars() {
+        let location = Location::from(
+            &Url::parse("https://gitlab.com/group/subgroup/starter/-/web#v1").unwrap(),
+            false,
+        )
+        .unwrap();
+        let remote = RemoteInfo {
+            revision: "abc123".to_string(),
+            ref_: "refs/tags/v1".to_string(),
+        };
+        let vars = Builtins {
+            shortlink: "gl:group/subgroup/starter/-/web#v1",
+            vendor: "gitlab",
+            location: &location,
+            remote: Some(&remote),
+            dest: Path::new("/work/my-app/./web/.."),
+            git_user_name: Some("Jane Doe".to_string()),
+            git_user_email: None,
+        }
+        .vars();
+
+        let get = |k: &str| vars.get(k).map(String::as_str);
+        assert_eq!(get("bp_dest_name"), Some("my-app"));
+        assert_eq!(
+            get("bp_shortlink"),
+            Some("gl:group/subgroup/starter/-/web#v1")
+        );
+        assert_eq!(get("bp_vendor"), Some("gitlab"));
+        assert_eq!(get("bp_owner"), Some("group/subgroup"));
+        assert_eq!(get("bp_repo_name"), Some("starter"));
+        assert_eq!(get("bp_ref"), Some("v1"));
+        assert_eq!(get("bp_revision"), Some("abc123"));
+        assert_eq!(get("bp_year").map(str::len), Some(4));
+        assert!(vars.contains_key("bp_date"));
+        assert_eq!(get("bp_git_user_name"), Some("Jane Doe"));
+        assert_eq!(get("bp_git_user_email"), Some(""));
+        assert!(vars.keys().all(|k| is_builtin(k)));
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(
+            parse_assignment("name=my app").unwrap(),
+            ("name".to_string(), "my app".to_string())
+        );
+        assert_eq!(
+            parse_assignment("url=a=b").unwrap(),
+            ("url".to_string(), "a=b".to_string())
+        );
+        assert_eq!(parse_assignment("empty=").unwrap().1, "");
+        assert!(parse_assignment("name").is_err());
+        assert!(parse_assignment("=value").is_err());
+    }
+
+    #[test]
+    fn test_load_file() {
+        let folder = tempfile::tempdir().unwrap();
+        let file = folder.path().join("answers.yaml");
+        fs::write(&file, "name: my-app\nport: 8080\nprivate: true\n").unwrap();
+        assert_eq!(
+            load_file(&file).unwrap(),
+            BTreeMap::from([
+                ("name".to_string(), "my-app".to_string()),
+                ("port".to_string(), "8080".to_string()),
+                ("private".to_string(), "true".to_string()),
+            ])
+        );
+
+        fs::write(&file, "name: [a, b]\n").unwrap();
+        assert!(matches!(
+            load_file(&file),
+            Err(Error::Config { path: Some(_), .. })
+        ));
+    }
+}
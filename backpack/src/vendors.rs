@@ -3,9 +3,21 @@ use crate::{
     data::{Archive, ArchiveRoot, Assets, Location},
     git::GitProvider,
 };
-use anyhow::Result as AnyResult;
+use crate::{Error, Result};
 use core::fmt::Debug;
+use lazy_static::lazy_static;
 use tracing;
+
+lazy_static! {
+    static ref GITHUB: CustomVendor = CustomVendor {
+        kind: "github".to_string(),
+        base: "github.com".to_string(),
+        transport: None,
+        ssh_port: None,
+        ssh_user: None,
+    };
+}
+
 pub struct Vendors<'a> {
     config: Option<&'a VendorsConfig>,
 }
@@ -16,39 +28,70 @@ impl<'a> Vendors<'a> {
     }
 
     #[tracing::instrument(name = "lookup_vendor", skip(self), err)]
-    pub fn lookup(&self, vendor: &str) -> AnyResult<Box<dyn Vendor>> {
-        let github = CustomVendor {
-            kind: "github".to_string(),
-            base: "github.com".to_string(),
-        };
-        let v = if vendor.is_empty() {
-            self.config
-                .and_then(|v| v.vendors_default.as_ref())
-                .or(Some(&github))
-        } else {
-            self.config
-                .and_then(|vendors| vendors.custom.as_ref())
-                .and_then(|h| h.get(vendor))
-        };
-        v.map_or_else(
+    pub fn lookup(&self, vendor: &str) -> Result<Box<dyn Vendor>> {
+        self.custom(vendor).map_or_else(
             || Vendors::lookup_table(vendor, None),
             |v| Vendors::lookup_table(&v.kind, Some(v.base.as_ref())),
         )
     }
-    fn lookup_table(token: &str, base: Option<&str>) -> AnyResult<Box<dyn Vendor>> {
+
+    /// Set what's configured for a vendor prefix on a location: the transport to
+    /// clone with, and how to reach its host over ssh
+    pub fn configure(&self, vendor: &str, location: &mut Location) {
+        if let Some(custom) = self.custom(vendor) {
+            location.transport = custom.transport;
+            location.ssh_port = custom.ssh_port;
+            location.ssh_user = custom.ssh_user.clone();
+        }
+    }
+
+    /// The configured vendor for a prefix, or for a host when a link is a full url
+    /// (`https://` or `git@`) to the `base` of a configured vendor
+    fn custom(&self, vendor: &str) -> Option<&CustomVendor> {
+        if vendor.is_empty() {
+            self.config
+                .and_then(|v| v.vendors_default.as_ref())
+                .or(Some(&GITHUB))
+        } else {
+            let matches_host = |v: &&CustomVendor| {
+                base_host(&v.base).is_some_and(|host| host.eq_ignore_ascii_case(vendor))
+            };
+            self.config.and_then(|vendors| {
+                let custom = vendors.custom.as_ref();
+                custom.and_then(|h| h.get(vendor)).or_else(|| {
+                    custom
+                        .into_iter()
+                        .flat_map(|h| h.values())
+                        .chain(vendors.vendors_default.as_ref())
+                        .find(matches_host)
+                })
+            })
+        }
+    }
+    fn lookup_table(token: &str, base: Option<&str>) -> Result<Box<dyn Vendor>> {
         match token {
             "gh" | "github.com" | "github" => Ok(Box::new(Github::new(base))),
             "gist.github.com" | "gist" => Ok(Box::new(GithubGist::new(base))),
             "gl" | "gitlab.com" | "gitlab" => Ok(Box::new(Gitlab::new(base))),
             "bb" | "bitbucket.org" | "bitbucket" => Ok(Box::new(BitBucket::new(base))),
-            _ => anyhow::bail!("no vendor found for: {}", token),
+            _ => Err(Error::config(format!("no vendor found for: {token}"))),
         }
     }
 }
 
+/// The host of a vendor `base`, which may carry credentials, a port and a path
+fn base_host(base: &str) -> Option<String> {
+    url::Url::parse(&format!("https://{base}"))
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
 pub trait Vendor {
     fn base(&self) -> &str;
 
+    /// What kind of vendor this is, e.g. `github`
+    fn kind(&self) -> &'static str;
+
     /// Resolve a location's assets.
     /// For example, a location my describe a github repo, and a branch, and its assets
     /// will be where you can download the code of that branch as a tarball.
@@ -56,7 +99,7 @@ pub trait Vendor {
     /// # Errors
     ///
     /// This function will return an error if network or other I/O is erroring.
-    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> AnyResult<Assets>;
+    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> Result<Assets>;
 }
 
 impl Debug for dyn Vendor {
@@ -84,24 +127,20 @@ impl Vendor for Github {
     fn base(&self) -> &str {
         self.base.as_str()
     }
+    fn kind(&self) -> &'static str {
+        "github"
+    }
     #[tracing::instrument(name = "github_resolve", skip_all, err)]
-    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> AnyResult<Assets> {
-        let gref = git.get_ref_or_default(location)?.ref_;
+    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> Result<Assets> {
+        let remote = git.get_ref_or_default(location)?;
+        let gref = remote.ref_.as_str();
         Ok(Assets {
             archive: Some(Archive {
-                url: format!(
-                    "https://{}{}/archive/{}.tar.gz",
-                    self.base(),
-                    location.path,
-                    gref,
-                ),
+                url: format!("{}/archive/{}.tar.gz", location.web_url(), gref),
                 root: ArchiveRoot::FirstFolder,
             }),
-            git: Some(format!(
-                "git@{}:{}.git",
-                self.base(),
-                location.path.trim_start_matches('/')
-            )),
+            git: Some(location.clone_url()),
+            remote: Some(remote),
         })
     }
 }
@@ -112,11 +151,15 @@ impl Vendor for LocalGit {
     fn base(&self) -> &str {
         "file"
     }
+    fn kind(&self) -> &'static str {
+        "local"
+    }
     #[tracing::instrument(name = "localgit_resolve", skip_all, err)]
-    fn resolve(&self, location: &Location, _git: &dyn GitProvider) -> AnyResult<Assets> {
+    fn resolve(&self, location: &Location, _git: &dyn GitProvider) -> Result<Assets> {
         Ok(Assets {
             archive: None,
             git: Some(location.url.clone()),
+            remote: None,
         })
     }
 }
@@ -136,30 +179,29 @@ impl Vendor for GithubGist {
     fn base(&self) -> &str {
         self.base.as_str()
     }
+    fn kind(&self) -> &'static str {
+        "gist"
+    }
     #[tracing::instrument(name = "github_gist_resolve", skip_all, err)]
-    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> AnyResult<Assets> {
+    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> Result<Assets> {
         let refs = git.ls_remote(location)?;
         let head = refs
             .iter()
             .find(|r| r.ref_ == "HEAD")
-            .ok_or_else(|| anyhow::anyhow!("no HEAD ref found"))?;
+            .ok_or_else(|| Error::NoSuchRef("HEAD".to_string()))?;
 
         Ok(Assets {
             archive: Some(Archive {
                 url: format!(
                     //   https://gist.github.com/jondot/15086f59dab44f30bb10f82ca09f4887/archive/44a751f50ea93f92c2bc6332e4de770429862888.zip
-                    "https://{}{}/archive/{}.tar.gz",
-                    self.base(),
-                    location.path,
+                    "{}/archive/{}.tar.gz",
+                    location.web_url(),
                     head.ref_,
                 ),
                 root: ArchiveRoot::FirstFolder,
             }),
-            git: Some(format!(
-                "git@{}:{}.git",
-                self.base(),
-                location.path.trim_start_matches('/')
-            )),
+            git: Some(location.clone_url()),
+            remote: Some(head.clone()),
         })
     }
 }
@@ -179,25 +221,21 @@ impl Vendor for BitBucket {
     fn base(&self) -> &str {
         self.base.as_str()
     }
+    fn kind(&self) -> &'static str {
+        "bitbucket"
+    }
 
     #[tracing::instrument(name = "bitbucket_resolve", skip_all, err)]
-    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> AnyResult<Assets> {
-        let gref = git.get_ref_or_default(location)?.ref_;
+    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> Result<Assets> {
+        let remote = git.get_ref_or_default(location)?;
+        let gref = remote.ref_.as_str();
         Ok(Assets {
             archive: Some(Archive {
-                url: format!(
-                    "https://{}{}/get/{}.tar.gz",
-                    self.base(),
-                    location.path,
-                    gref,
-                ),
+                url: format!("{}/get/{}.tar.gz", location.web_url(), gref),
                 root: ArchiveRoot::FirstFolder,
             }),
-            git: Some(format!(
-                "git@{}:{}.git",
-                self.base(),
-                location.path.trim_start_matches('/')
-            )),
+            git: Some(location.clone_url()),
+            remote: Some(remote),
         })
     }
 }
@@ -218,28 +256,28 @@ impl Vendor for Gitlab {
     fn base(&self) -> &str {
         self.base.as_str()
     }
+    fn kind(&self) -> &'static str {
+        "gitlab"
+    }
 
     #[tracing::instrument(name = "gitlab_resolve", skip_all, err)]
-    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> AnyResult<Assets> {
-        let gref = git.get_ref_or_default(location)?.ref_;
+    fn resolve(&self, location: &Location, git: &dyn GitProvider) -> Result<Assets> {
+        let remote = git.get_ref_or_default(location)?;
+        let gref = remote.ref_.as_str();
         let gref_file = gref.replace('/', "-");
         Ok(Assets {
             archive: Some(Archive {
                 url: format!(
-                    "https://{}{}/-/archive/{}/{}-{}.tar.gz",
-                    self.base(),
-                    location.path,
+                    "{}/-/archive/{}/{}-{}.tar.gz",
+                    location.web_url(),
                     gref,
                     location.project,
                     gref_file,
                 ),
                 root: ArchiveRoot::FirstFolder,
             }),
-            git: Some(format!(
-                "git@{}:{}.git",
-                self.base(),
-                location.path.trim_start_matches('/')
-            )),
+            git: Some(location.clone_url()),
+            remote: Some(remote),
         })
     }
 }
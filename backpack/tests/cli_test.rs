@@ -0,0 +1,63 @@
+#![cfg(feature = "cli")]
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=bp", "-c", "user.email=bp@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success(), "git {args:?} failed");
+}
+
+#[test]
+fn test_json_output_parses() {
+    let out = tempfile::tempdir().unwrap();
+    let template = out.path().join("template");
+    fs::create_dir_all(&template).unwrap();
+    fs::write(template.join("README.md"), "hello").unwrap();
+    fs::write(
+        template.join(".backpack-project.yaml"),
+        r#"
+project:
+  shortlink: starter
+  actions:
+  - name: greet
+    run: "true"
+"#,
+    )
+    .unwrap();
+    git(&template, &["init", "-q"]);
+    git(&template, &["add", "."]);
+    git(&template, &["commit", "-q", "-m", "initial"]);
+
+    let dest = out.path().join("generated");
+    let output = Command::new(env!("CARGO_BIN_EXE_bp"))
+        .args([
+            template.to_str().unwrap(),
+            dest.to_str().unwrap(),
+            "--json",
+            "--headless",
+        ])
+        .env("BP_FOLDER", out.path().join("global"))
+        .env("BP_CONF", out.path().join("none.yaml"))
+        .env("BP_GIT", "cmd")
+        .current_dir(out.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // nothing but the report on stdout, even with actions running
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"].as_array().unwrap().len(), 1);
+    assert_eq!(report["actions"][0]["name"], "greet");
+    assert!(dest.join("README.md").exists());
+}
@@ -1,11 +1,25 @@
 use backpack::data::Location;
-use backpack::git::{GitCmd, GitProvider};
+use backpack::git::GitProvider;
 use pretty_assertions::assert_eq;
+use serial_test::serial;
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs};
+use tempfile::TempDir;
 use url::Url;
 
+/// The provider under test, picked with `BP_GIT=cmd|gitoxide` the way `bp` does
+fn provider() -> Box<dyn GitProvider> {
+    assert!(
+        cfg!(feature = "gitoxide") || env::var("BP_GIT").as_deref() != Ok("gitoxide"),
+        "BP_GIT=gitoxide needs the 'gitoxide' feature"
+    );
+    backpack::git::provider()
+}
+
 #[test]
 fn test_mainbranch_resolving() {
-    let git = GitCmd::default();
+    let git = provider();
     let location = Location::from(
         &Url::parse("https://github.com/jondot/hygen").unwrap(),
         false,
@@ -17,7 +31,7 @@ fn test_mainbranch_resolving() {
 
 #[test]
 fn test_tags_resolving() {
-    let git = GitCmd::default();
+    let git = provider();
     let location = Location::from(
         &Url::parse("https://github.com/jondot/hygen#v6.2.0").unwrap(),
         false,
@@ -29,7 +43,7 @@ fn test_tags_resolving() {
 
 #[test]
 fn test_branches_resolving() {
-    let git = GitCmd::default();
+    let git = provider();
     let location = Location::from(
         &Url::parse("https://github.com/jondot/hygen#gh-pages").unwrap(),
         false,
@@ -38,3 +52,91 @@ fn test_branches_resolving() {
     let res = git.get_ref_or_default(&location).unwrap().ref_;
     assert_eq!(res, "refs/heads/gh-pages");
 }
+
+//
+// against local `file://` repositories
+//
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=bp", "-c", "user.email=bp@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success(), "git {args:?} failed");
+}
+
+/// A repo with a `master` default branch, a `v1.0.0` annotated tag and a `feature` branch
+fn fixture_repo() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = dir.path();
+    git(repo, &["init", "-q"]);
+    git(repo, &["symbolic-ref", "HEAD", "refs/heads/master"]);
+    fs::write(repo.join("README.md"), "master").unwrap();
+    git(repo, &["add", "."]);
+    git(repo, &["commit", "-q", "-m", "initial"]);
+    git(repo, &["tag", "-a", "v1.0.0", "-m", "release"]);
+    git(repo, &["checkout", "-q", "-b", "feature"]);
+    fs::write(repo.join("feature.txt"), "feature").unwrap();
+    git(repo, &["add", "."]);
+    git(repo, &["commit", "-q", "-m", "feature"]);
+    git(repo, &["checkout", "-q", "master"]);
+    dir
+}
+
+fn local_location(repo: &Path, gref: &str) -> Location {
+    let mut url = Url::from_file_path(repo).unwrap();
+    url.set_fragment(gref.strip_prefix('#'));
+    Location::from(&url, true).unwrap()
+}
+
+#[test]
+fn test_local_no_such_ref() {
+    let repo = fixture_repo();
+    assert!(matches!(
+        provider().get_ref_or_default(&local_location(repo.path(), "#nope")),
+        Err(backpack::Error::NoSuchRef(_))
+    ));
+}
+
+#[test]
+fn test_local_shallow_clone() {
+    let repo = fixture_repo();
+    let git = provider();
+    let out = tempfile::tempdir().unwrap();
+    let dest = out.path().join("default");
+    git.shallow_clone(&local_location(repo.path(), ""), dest.to_str().unwrap())
+        .unwrap();
+    assert!(dest.join("README.md").exists());
+    assert!(!dest.join("feature.txt").exists());
+
+    let dest = out.path().join("feature");
+    git.shallow_clone(
+        &local_location(repo.path(), "#feature"),
+        dest.to_str().unwrap(),
+    )
+    .unwrap();
+    assert!(dest.join("feature.txt").exists());
+}
+
+#[test]
+#[serial]
+fn test_local_url() {
+    let repo = fixture_repo();
+    git(
+        repo.path(),
+        &[
+            "remote",
+            "add",
+            "origin",
+            "https://example.com/org/repo.git",
+        ],
+    );
+    let current_dir = env::current_dir().unwrap();
+    env::set_current_dir(repo.path()).unwrap();
+    let url = provider().get_local_url();
+    env::set_current_dir(current_dir).unwrap();
+    assert_eq!(url.unwrap(), "https://example.com/org/repo.git");
+}
@@ -1,13 +1,23 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 
 use anyhow::Result;
 use backpack::config::Config;
-use backpack::data::{CopyMode, Opts};
-use backpack::run::{Runner, RunnerEvents};
+use backpack::content::{FilePlan, Plan};
+use backpack::data::{CopyMode, Location, OnConflict, Opts, Transport};
+use backpack::git::{GitProvider, RemoteInfo};
+use backpack::manifest::Manifest;
+use backpack::merge::{MergeOutcome, BASE_FOLDER};
+use backpack::run::{Runner, RunnerBuilder, RunnerEvents};
+use backpack::templates::CopyResult;
+use backpack::ui::{OverwriteChoice, Prompter};
 use insta::assert_debug_snapshot;
+use interactive_actions::data::ActionResult;
 use requestty_ui::events::KeyCode;
 use serial_test::serial;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 use walkdir::{DirEntry, WalkDir};
 
 fn ensure_no_config() {
@@ -27,6 +37,8 @@ fn list_folder(dest: &Path) -> Vec<String> {
         .filter_map(Result::ok)
         .filter(|file| file.metadata().unwrap().is_file())
         .map(DirEntry::into_path)
+        // merge bases mirror the generated files
+        .filter(|p| !p.components().any(|c| c.as_os_str() == BASE_FOLDER))
         .map(|p| {
             p.to_string_lossy()
                 .replace(env::current_dir().unwrap().to_str().unwrap(), "")
@@ -70,13 +82,10 @@ fn run(
             shortlink,
             dest,
             &Opts {
-                show_progress: false,
-                overwrite: false,
                 is_git,
-                no_cache: false,
                 always_yes: true,
-                config_file: None,
                 mode,
+                ..Opts::default()
             },
             &events,
         )?;
@@ -85,13 +94,9 @@ fn run(
             shortlink,
             dest,
             &Opts {
-                show_progress: false,
-                overwrite: false,
-                is_git: false,
-                no_cache: false,
                 always_yes: true,
-                config_file: None,
                 mode,
+                ..Opts::default()
             },
         )?;
     };
@@ -339,3 +344,963 @@ projects:
         }),
     ));
 }
+
+/// One of our local fixtures
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Clones by copying whatever is in `folder` at the time, so runner tests don't need the network
+struct FixtureGit {
+    folder: PathBuf,
+}
+impl GitProvider for FixtureGit {
+    fn get_ref_or_default(&self, _location: &Location) -> backpack::Result<RemoteInfo> {
+        Ok(RemoteInfo {
+            revision: "rev".to_string(),
+            ref_: "refs/heads/master".to_string(),
+        })
+    }
+    fn ls_remote(&self, location: &Location) -> backpack::Result<Vec<RemoteInfo>> {
+        Ok(vec![self.get_ref_or_default(location)?])
+    }
+    fn shallow_clone(&self, _location: &Location, out: &str) -> backpack::Result<()> {
+        fs::create_dir_all(out)?;
+        fs_extra::dir::copy(
+            &self.folder,
+            out,
+            &fs_extra::dir::CopyOptions {
+                content_only: true,
+                ..Default::default()
+            },
+        )
+        .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+    fn get_local_url(&self) -> backpack::Result<String> {
+        Ok(String::new())
+    }
+    fn config_value(&self, key: &str) -> Option<String> {
+        (key == "user.name").then(|| "Fixture User".to_string())
+    }
+}
+
+/// A runner generating from the `template` folder, which `SHORTLINK` in `config` points to
+fn fixture_builder(config: &str, template: &Path) -> RunnerBuilder {
+    Runner::builder()
+        .git(Box::new(FixtureGit {
+            folder: template.to_path_buf(),
+        }))
+        .cache_path(env::temp_dir().join("bp-runner-test-cache"))
+        .config(
+            Config::from_text(&config.replace("SHORTLINK", template.to_str().unwrap())).unwrap(),
+        )
+}
+
+/// Like [`fixture_builder`], never asking anything
+fn fixture_runner(config: &str, template: &Path) -> Runner {
+    fixture_builder(config, template)
+        .prompt(Box::new(RecordingPrompt::default()))
+        .build()
+}
+
+/// Never asks anything, and records what was generated
+#[derive(Default)]
+struct RecordingPrompt {
+    done: Rc<RefCell<Vec<String>>>,
+}
+impl Prompter for RecordingPrompt {
+    fn fill_missing(
+        &mut self,
+        shortlink: Option<&str>,
+        dest: Option<&str>,
+        _mode: &CopyMode,
+    ) -> Result<(String, Option<String>, bool)> {
+        Ok((
+            shortlink.unwrap_or_default().to_string(),
+            dest.map(ToString::to_string),
+            false,
+        ))
+    }
+    fn are_you_sure(&mut self, _text: &str) -> Result<bool> {
+        anyhow::bail!("should not ask")
+    }
+    fn confirm_overwrite(
+        &mut self,
+        _file: &Path,
+        _new_content: Option<&str>,
+    ) -> Result<OverwriteChoice> {
+        anyhow::bail!("should not ask")
+    }
+    fn say_resolving(&self) {}
+    fn say_fetching(&self) {}
+    fn say_unpacking(&self) {}
+    fn say_action(&self, _name: &str) {}
+    fn say_plan(&self, _plan: &Plan) {}
+    fn say_done(
+        &self,
+        res: &[CopyResult],
+        _maybe_actions: Option<&Vec<ActionResult>>,
+        _unmatched_swaps: &[String],
+    ) {
+        self.done
+            .borrow_mut()
+            .extend(res.iter().map(|r| r.dest.display().to_string()));
+    }
+}
+
+#[test]
+#[serial]
+fn test_run_with_builder() {
+    let out = tempfile::tempdir().unwrap();
+    let dest = out.path().join("generated");
+    let done = Rc::new(RefCell::new(vec![]));
+
+    let runner = fixture_builder(
+        r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: SWAP_ME
+      val: swapped
+"#,
+        &fixture("content"),
+    )
+    .prompt(Box::new(RecordingPrompt { done: done.clone() }))
+    .build();
+
+    let report = runner
+        .run(Some("fixture"), dest.to_str(), &Opts::default())
+        .unwrap();
+
+    assert!(dest.join("swapped/hey.txt").exists());
+    assert_eq!(
+        *done.borrow(),
+        vec![dest.join("swapped/hey.txt").display().to_string()]
+    );
+
+    assert!(!report.cancelled);
+    assert_eq!(report.shortlink, "fixture");
+    assert_eq!(report.destination, dest);
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].dest, dest.join("swapped/hey.txt"));
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["files"][0]["op"], serde_json::json!({ "Rendered": 1 }));
+    assert_eq!(json["location"]["is_git"], serde_json::json!(true));
+}
+
+#[test]
+#[serial]
+fn test_run_report_redacts_credentials() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+    let dest = out.path().join("generated");
+
+    let runner = fixture_runner(
+        r#"
+vendors:
+  custom:
+    corp:
+      kind: gitlab
+      base: oauth2:s3cret@gitlab.example.com:8443
+      transport: https
+projects:
+  fixture:
+    shortlink: corp:group/content
+    is_git: true
+"#,
+        &fixture("content"),
+    );
+    let report = runner
+        .run(Some("fixture"), dest.to_str(), &Opts::default())
+        .unwrap();
+
+    // the clone url carries the password, the report doesn't
+    assert!(report.assets.git.as_ref().unwrap().contains("s3cret"));
+    let json = serde_json::to_string(&report).unwrap();
+    assert!(!json.contains("s3cret"), "{json}");
+    assert!(json.contains("https://oauth2@gitlab.example.com:8443/group/content.git"));
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_applies_template_project_config() {
+    let out = tempfile::tempdir().unwrap();
+    let dest = out.path().join("generated");
+
+    let runner = fixture_runner(
+        r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: AUTHOR
+      val: me
+"#,
+        &fixture("template"),
+    );
+
+    let report = runner
+        .run(Some("fixture"), dest.to_str(), &Opts::default())
+        .unwrap();
+
+    // the template's swaps apply, the user's win for the same key
+    assert_eq!(
+        fs::read_to_string(dest.join("README.md")).unwrap(),
+        "# template-app by me\n\nGenerated from a template.\n"
+    );
+    // and neither the template's own setup nor what it ignores is generated
+    assert!(!dest.join(".backpack-project.yaml").exists());
+    assert!(!dest.join(".backpackignore").exists());
+    assert!(!dest.join("LICENSE").exists());
+    assert_eq!(report.files.len(), 1);
+}
+
+#[test]
+#[serial]
+fn test_run_dry_run() {
+    let out = tempfile::tempdir().unwrap();
+    let dest = out.path().join("generated");
+    let ran = out.path().join("ran.txt");
+
+    let runner = fixture_runner(
+        &r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    actions:
+    - name: touch
+      run: touch RAN
+"#
+        .replace("RAN", ran.to_str().unwrap()),
+        &fixture("template"),
+    );
+
+    let report = runner
+        .run(
+            Some("fixture"),
+            dest.to_str(),
+            &Opts {
+                dry_run: true,
+                ..Opts::default()
+            },
+        )
+        .unwrap();
+
+    // nothing written, nothing run
+    assert!(!dest.exists());
+    assert!(!ran.exists());
+    assert!(report.files.is_empty());
+
+    let plan = report.plan.unwrap();
+    assert_eq!(plan.files.len(), 1);
+    assert_eq!(plan.files[0].dest, dest.join("README.md"));
+    assert_eq!(plan.files[0].plan, FilePlan::Create);
+    assert_eq!(plan.files[0].swaps, Some(2));
+    assert_eq!(plan.actions.len(), 1);
+    assert_eq!(plan.actions[0].name, "touch");
+
+    // an existing file would be asked about
+    fs::create_dir_all(&dest).unwrap();
+    fs::write(dest.join("README.md"), "mine").unwrap();
+    let report = runner
+        .run(
+            Some("fixture"),
+            dest.to_str(),
+            &Opts {
+                dry_run: true,
+                ..Opts::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.plan.unwrap().files[0].plan, FilePlan::Ask);
+    assert_eq!(fs::read_to_string(dest.join("README.md")).unwrap(), "mine");
+}
+
+fn run_template(dest: &Path, author: &str, merge: bool) -> backpack::run::GenerationReport {
+    fixture_runner(
+        &r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: AUTHOR
+      val: WHO
+"#
+        .replace("WHO", author),
+        &fixture("template"),
+    )
+    .run(
+        Some("fixture"),
+        dest.to_str(),
+        &Opts {
+            mode: CopyMode::Apply,
+            merge,
+            ..Opts::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+#[serial]
+fn test_run_merge() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+    let dest = out.path().join("generated");
+    let readme = dest.join("README.md");
+
+    // bases are only kept once merging is asked for
+    run_template(&dest, "me", false);
+    assert!(!dest.join(BASE_FOLDER).exists());
+    run_template(&dest, "me", true);
+    assert_eq!(
+        fs::read_to_string(&readme).unwrap(),
+        "# template-app by me\n\nGenerated from a template.\n"
+    );
+
+    // local edits and template changes on different lines merge cleanly
+    fs::write(&readme, "# template-app by me\n\nlocal notes\n").unwrap();
+    let report = run_template(&dest, "you", true);
+    assert_eq!(
+        fs::read_to_string(&readme).unwrap(),
+        "# template-app by you\n\nlocal notes\n"
+    );
+    assert_eq!(report.files[0].merge, Some(MergeOutcome::Clean));
+
+    // both changing the same line conflicts
+    fs::write(&readme, "# my app\n\nlocal notes\n").unwrap();
+    let report = run_template(&dest, "them", true);
+    assert_eq!(
+        fs::read_to_string(&readme).unwrap(),
+        "<<<<<<< ours\n# my app\n=======\n# template-app by them\n>>>>>>> theirs\n\nlocal notes\n"
+    );
+    assert_eq!(report.files[0].merge, Some(MergeOutcome::Conflict));
+
+    // bases are kept with the project, so merging still works once it's moved
+    assert!(dest.join(BASE_FOLDER).join("README.md").exists());
+    assert!(!out.path().join("global").exists());
+    let moved = out.path().join("moved");
+    fs::rename(&dest, &moved).unwrap();
+    fs::write(
+        moved.join("README.md"),
+        "# template-app by them
+
+local notes
+",
+    )
+    .unwrap();
+    let report = run_template(&moved, "us", true);
+    assert_eq!(
+        fs::read_to_string(moved.join("README.md")).unwrap(),
+        "# template-app by us
+
+local notes
+"
+    );
+    assert_eq!(report.files[0].merge, Some(MergeOutcome::Clean));
+
+    env::remove_var("BP_FOLDER");
+}
+
+fn folder_runner(template: &Path, app: &str) -> Runner {
+    fixture_runner(
+        &r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: APP
+      val: WHAT
+"#
+        .replace("WHAT", app),
+        template,
+    )
+}
+
+#[test]
+#[serial]
+fn test_run_update() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+    let template = out.path().join("template");
+    let dest = out.path().join("generated");
+    fs::create_dir_all(&template).unwrap();
+    fs::write(template.join("a.txt"), "APP one\n").unwrap();
+    fs::write(template.join("b.txt"), "APP two\n").unwrap();
+    let opts = Opts::default();
+
+    folder_runner(&template, "first")
+        .run(Some("fixture"), dest.to_str(), &opts)
+        .unwrap();
+    let manifest = Manifest::load(&dest).unwrap();
+    assert_eq!(manifest.shortlink, "fixture");
+    // generated from a local folder, there's no revision to record
+    assert_eq!(manifest.revision, None);
+    assert_eq!(manifest.swaps.get("APP").map(String::as_str), Some("first"));
+    assert_eq!(
+        manifest.files.keys().collect::<Vec<_>>(),
+        vec![Path::new("a.txt"), Path::new("b.txt")]
+    );
+
+    // the template moves on, and b.txt is edited locally
+    fs::write(template.join("a.txt"), "APP one, v2\n").unwrap();
+    fs::write(template.join("b.txt"), "APP two, v2\n").unwrap();
+    fs::write(template.join("c.txt"), "APP three\n").unwrap();
+    fs::write(dest.join("b.txt"), "mine\n").unwrap();
+
+    // recorded swap values win over the configuration changing since
+    let report = folder_runner(&template, "second")
+        .update(dest.to_str(), &opts)
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(dest.join("a.txt")).unwrap(),
+        "first one, v2\n"
+    );
+    assert_eq!(fs::read_to_string(dest.join("b.txt")).unwrap(), "mine\n");
+    assert_eq!(
+        fs::read_to_string(dest.join("c.txt")).unwrap(),
+        "first three\n"
+    );
+    assert_eq!(report.kept, vec![dest.join("b.txt")]);
+
+    // the edited file stays edited for the next update
+    let manifest = Manifest::load(&dest).unwrap();
+    assert!(!manifest.is_pristine(&dest, &dest.join("b.txt")).unwrap());
+    assert!(manifest.is_pristine(&dest, &dest.join("c.txt")).unwrap());
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_update_replays_fetch() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+    let template = out.path().join("template");
+    let dest = out.path().join("generated");
+    fs::create_dir_all(&template).unwrap();
+    fs::write(template.join("README.md"), "hello").unwrap();
+    fs::write(
+        template.join(".backpack-project.yaml"),
+        r#"
+project:
+  shortlink: org/starter
+  actions:
+  - name: mark
+    run: touch ran.txt
+"#,
+    )
+    .unwrap();
+    let runner = fixture_runner("projects: {}", &template);
+
+    runner
+        .run(
+            Some("gh:org/starter"),
+            dest.to_str(),
+            &Opts {
+                is_git: true,
+                transport: Some(Transport::Ssh),
+                trust: true,
+                ..Opts::default()
+            },
+        )
+        .unwrap();
+    let manifest = Manifest::load(&dest).unwrap();
+    assert!(manifest.is_git);
+    assert_eq!(manifest.transport, Some(Transport::Ssh));
+    assert!(dest.join("ran.txt").exists());
+    fs::remove_file(dest.join("ran.txt")).unwrap();
+
+    // fetched with git again rather than as an archive, and nothing runs again
+    fs::write(template.join("README.md"), "hello, v2").unwrap();
+    runner.update(dest.to_str(), &Opts::default()).unwrap();
+    assert_eq!(
+        fs::read_to_string(dest.join("README.md")).unwrap(),
+        "hello, v2"
+    );
+    assert!(!dest.join("ran.txt").exists());
+
+    env::remove_var("BP_FOLDER");
+}
+
+fn run_template_with(
+    config: &str,
+    dest: &Path,
+) -> backpack::Result<backpack::run::GenerationReport> {
+    run_template_opts(config, dest, &Opts::default())
+}
+
+fn run_template_opts(
+    config: &str,
+    dest: &Path,
+    opts: &Opts,
+) -> backpack::Result<backpack::run::GenerationReport> {
+    fixture_runner(config, &fixture("template")).run(Some("fixture"), dest.to_str(), opts)
+}
+
+#[test]
+#[serial]
+fn test_run_unmatched_swaps() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+
+    let dest = out.path().join("warned");
+    let report = run_template_with(
+        r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: __V_PROJECT_NAME__
+      val: gone
+"#,
+        &dest,
+    )
+    .unwrap();
+    assert_eq!(report.unmatched_swaps, vec!["__V_PROJECT_NAME__"]);
+    assert!(dest.join("README.md").exists());
+
+    // skipped files are not written, so what they'd match doesn't count
+    let config = r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: AUTHOR
+      val: me
+"#;
+    for dry_run in [true, false] {
+        let report = run_template_opts(
+            config,
+            &dest,
+            &Opts {
+                dry_run,
+                on_conflict: Some(OnConflict::Skip),
+                ..Opts::default()
+            },
+        )
+        .unwrap();
+        let unmatched = match report.plan {
+            Some(plan) => plan.unmatched_swaps,
+            None => report.unmatched_swaps,
+        };
+        assert_eq!(unmatched, vec!["APP_NAME", "AUTHOR"]);
+    }
+
+    // a required swap that misses fails before anything is written
+    let dest = out.path().join("failed");
+    let err = run_template_with(
+        r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: __V_PROJECT_NAME__
+      val: gone
+      required: true
+    - key: AUTHOR
+      val: me
+      required: true
+"#,
+        &dest,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "configuration error: required swap(s) did not match anything: '__V_PROJECT_NAME__'"
+    );
+    assert!(!dest.join("README.md").exists());
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_builtin_vars() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+
+    let dest = out.path().join("my-app");
+    let report = run_template_with(
+        r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    swaps:
+    - key: APP_NAME
+      val_template: "{{bp_dest_name}}"
+    - key: AUTHOR
+      val_template: "{{bp_vendor}} in {{bp_year}}"
+"#,
+        &dest,
+    )
+    .unwrap();
+    assert_eq!(report.vars.get("bp_dest_name").unwrap(), "my-app");
+    // the git identity comes from the git provider, not the user's own configuration
+    assert_eq!(report.vars.get("bp_git_user_name").unwrap(), "Fixture User");
+    assert_eq!(report.vars.get("bp_git_user_email").unwrap(), "");
+    let readme = fs::read_to_string(dest.join("README.md")).unwrap();
+    assert!(readme.starts_with(&format!(
+        "# my-app by local in {}",
+        report.vars.get("bp_year").unwrap()
+    )));
+
+    // built-ins are worked out again on every run, only answers are recorded
+    let manifest = Manifest::load(&dest).unwrap();
+    assert!(manifest.vars.keys().all(|k| !k.starts_with("bp_")));
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_non_interactive_vars() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+
+    let config = r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    actions:
+    - name: ask name
+      interaction:
+        kind: input
+        prompt: name of your app
+        out: name
+    - name: ask author
+      interaction:
+        kind: input
+        prompt: who are you
+        out: author
+    - name: are you sure
+      interaction:
+        kind: confirm
+        prompt: go on?
+    swaps:
+    - key: APP_NAME
+      val_template: "{{name}}"
+    - key: AUTHOR
+      val_template: "{{author}}"
+"#;
+    let mut opts = Opts {
+        vars: BTreeMap::from([("name".to_string(), "given".to_string())]),
+        non_interactive: true,
+        ..Opts::default()
+    };
+
+    // every question left is listed, nothing is generated
+    let dest = out.path().join("missing");
+    let err = run_template_opts(config, &dest, &opts).unwrap_err();
+    assert!(matches!(
+        &err,
+        backpack::Error::MissingVars(missing) if missing == &["author", "(action 'are you sure')"]
+    ));
+    assert!(!dest.exists());
+
+    // with all answers given up front, nothing is asked
+    let config = config.replace(
+        "        kind: confirm\n",
+        "        kind: confirm\n        out: sure\n",
+    );
+    opts.vars.insert("author".to_string(), "me".to_string());
+    opts.vars.insert("sure".to_string(), "true".to_string());
+    let dest = out.path().join("answered");
+    let report = run_template_opts(&config, &dest, &opts).unwrap();
+    assert_eq!(report.vars.get("author").unwrap(), "me");
+    let readme = fs::read_to_string(dest.join("README.md")).unwrap();
+    assert!(readme.starts_with("# given by me"));
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_headless() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+
+    // no prompt of our own, the headless one is used
+    let runner = fixture_builder(
+        "projects:\n  fixture:\n    shortlink: SHORTLINK\n",
+        &fixture("template"),
+    )
+    .build();
+    let dest = out.path().join("headless");
+    let mut opts = Opts {
+        headless: true,
+        ..Opts::default()
+    };
+
+    // nothing to ask on a fresh destination
+    runner.run(Some("fixture"), dest.to_str(), &opts).unwrap();
+    assert!(dest.join("README.md").exists());
+
+    // asking for a shortlink, a destination, or about an existing file, fails instead of prompting
+    assert!(matches!(
+        runner.run(None, dest.to_str(), &opts),
+        Err(backpack::Error::Headless(_))
+    ));
+    assert!(matches!(
+        runner.run(Some("fixture"), None, &opts),
+        Err(backpack::Error::Headless(what)) if what == "destination"
+    ));
+    assert!(matches!(
+        runner.run(Some("fixture"), dest.to_str(), &opts),
+        Err(backpack::Error::Headless(_))
+    ));
+
+    // conflicts are settled by policy
+    fs::write(dest.join("README.md"), "edited").unwrap();
+    opts.on_conflict = Some(OnConflict::Skip);
+    runner.run(Some("fixture"), dest.to_str(), &opts).unwrap();
+    assert_eq!(
+        fs::read_to_string(dest.join("README.md")).unwrap(),
+        "edited"
+    );
+
+    opts.on_conflict = Some(OnConflict::Fail);
+    assert!(matches!(
+        runner.run(Some("fixture"), dest.to_str(), &opts),
+        Err(backpack::Error::Conflict(_))
+    ));
+
+    opts.on_conflict = Some(OnConflict::Backup);
+    runner.run(Some("fixture"), dest.to_str(), &opts).unwrap();
+    assert_eq!(
+        fs::read_to_string(dest.join("README.md.bak")).unwrap(),
+        "edited"
+    );
+    assert!(fs::read_to_string(dest.join("README.md"))
+        .unwrap()
+        .ends_with("Generated from a template.\n"));
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_conditional_actions() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+
+    let config = r#"
+projects:
+  fixture:
+    shortlink: SHORTLINK
+    actions:
+    - name: ask for a license
+      if: "{{ ci == 'no' }}"
+      interaction:
+        kind: input
+        prompt: which license?
+        out: license
+    - name: set up ci
+      if: ci == 'yes'
+      run: touch ci.yml
+    - name: set up docker
+      if: docker == 'yes'
+      run: touch Dockerfile
+"#;
+    let dest = out.path().join("conditional");
+    let report = run_template_opts(
+        config,
+        &dest,
+        &Opts {
+            vars: BTreeMap::from([
+                ("ci".to_string(), "yes".to_string()),
+                ("docker".to_string(), "no".to_string()),
+            ]),
+            non_interactive: true,
+            ..Opts::default()
+        },
+    )
+    .unwrap();
+
+    // the license question doesn't count as missing, its condition doesn't hold
+    assert_eq!(
+        report
+            .actions
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["set up ci"]
+    );
+    assert!(dest.join("ci.yml").exists());
+    assert!(!dest.join("Dockerfile").exists());
+
+    env::remove_var("BP_FOLDER");
+}
+
+/// Answers whether to trust a template from a script, failing on anything else
+struct TrustPrompt {
+    answers: Rc<RefCell<Vec<bool>>>,
+}
+impl Prompter for TrustPrompt {
+    fn fill_missing(
+        &mut self,
+        shortlink: Option<&str>,
+        dest: Option<&str>,
+        _mode: &CopyMode,
+    ) -> Result<(String, Option<String>, bool)> {
+        Ok((
+            shortlink.unwrap_or_default().to_string(),
+            dest.map(ToString::to_string),
+            false,
+        ))
+    }
+    fn are_you_sure(&mut self, _text: &str) -> Result<bool> {
+        anyhow::bail!("should not ask")
+    }
+    fn confirm_trust(&mut self, template: &str, commands: &[String]) -> Result<bool> {
+        assert_eq!(template, "github.com/org/starter");
+        assert_eq!(commands[0], "touch ran.txt");
+        Ok(self.answers.borrow_mut().remove(0))
+    }
+    fn confirm_overwrite(
+        &mut self,
+        _file: &Path,
+        _new_content: Option<&str>,
+    ) -> Result<OverwriteChoice> {
+        anyhow::bail!("should not ask")
+    }
+    fn say_resolving(&self) {}
+    fn say_fetching(&self) {}
+    fn say_unpacking(&self) {}
+    fn say_action(&self, _name: &str) {}
+    fn say_plan(&self, _plan: &Plan) {}
+    fn say_done(
+        &self,
+        _res: &[CopyResult],
+        _maybe_actions: Option<&Vec<ActionResult>>,
+        _unmatched_swaps: &[String],
+    ) {
+    }
+}
+
+#[test]
+#[serial]
+fn test_run_trust_template_actions() {
+    let out = tempfile::tempdir().unwrap();
+    let template = out.path().join("template");
+    fs::create_dir_all(&template).unwrap();
+    fs::write(template.join("README.md"), "hello").unwrap();
+    fs::write(
+        template.join(".backpack-project.yaml"),
+        r#"
+project:
+  shortlink: org/starter
+  actions:
+  - name: mark
+    run: touch ran.txt
+"#,
+    )
+    .unwrap();
+
+    let answers = Rc::new(RefCell::new(vec![]));
+    let generate = |name: &str, config: &str, trust: bool| {
+        fixture_builder(config, &template)
+            .prompt(Box::new(TrustPrompt {
+                answers: answers.clone(),
+            }))
+            .build()
+            .run(
+                Some("gh:org/starter"),
+                out.path().join(name).to_str(),
+                &Opts {
+                    is_git: true,
+                    no_cache: true,
+                    trust,
+                    ..Opts::default()
+                },
+            )
+            .unwrap();
+        out.path().join(name).join("ran.txt").exists()
+    };
+    let global = out.path().join("global");
+    env::set_var("BP_FOLDER", &global);
+
+    // declined, files are generated but nothing runs or is remembered
+    answers.borrow_mut().push(false);
+    assert!(!generate("declined", "projects: {}", false));
+    assert!(out.path().join("declined/README.md").exists());
+    assert!(!global.join("trusted.yaml").exists());
+
+    // trusted once, for this revision
+    answers.borrow_mut().push(true);
+    assert!(generate("trusted", "projects: {}", false));
+    assert!(generate("again", "projects: {}", false));
+    assert!(answers.borrow().is_empty());
+
+    // same revision, other commands: asked again
+    let mut project = fs::read_to_string(template.join(".backpack-project.yaml")).unwrap();
+    project.push_str("  - name: more\n    run: touch more.txt\n");
+    fs::write(template.join(".backpack-project.yaml"), project).unwrap();
+    answers.borrow_mut().push(false);
+    assert!(!generate("changed", "projects: {}", false));
+    assert!(answers.borrow().is_empty());
+
+    // hosts out of the policy never run, even when trusted before
+    let policy = "trust:\n  hosts:\n  - gitlab.com\n";
+    assert!(!generate("policy", policy, false));
+
+    // --trust runs without asking, and without remembering
+    env::set_var("BP_FOLDER", out.path().join("other-global"));
+    assert!(generate("flag", "projects: {}", true));
+    assert!(!out.path().join("other-global/trusted.yaml").exists());
+
+    env::remove_var("BP_FOLDER");
+}
+
+#[test]
+#[serial]
+fn test_run_malicious_swap() {
+    let out = tempfile::tempdir().unwrap();
+    env::set_var("BP_FOLDER", out.path().join("global"));
+
+    let runner = fixture_runner(
+        "projects:\n  fixture:\n    shortlink: SHORTLINK\n",
+        &fixture("malicious-swap"),
+    );
+    let run = |dest: &Path, name: &str, dry_run: bool| {
+        runner.run(
+            Some("fixture"),
+            dest.to_str(),
+            &Opts {
+                always_yes: true,
+                dry_run,
+                vars: BTreeMap::from([("name".to_string(), name.to_string())]),
+                non_interactive: true,
+                ..Opts::default()
+            },
+        )
+    };
+
+    let dest = out.path().join("generated");
+    run(&dest, "my-app", false).unwrap();
+    assert!(dest.join("my-app/README.md").exists());
+
+    for dry_run in [true, false] {
+        let dest = out.path().join(format!("escaped-{dry_run}"));
+        match run(&dest, "../../outside", dry_run) {
+            Err(backpack::Error::UnsafePath { path, cause }) => {
+                assert!(path.ends_with("outside/README.md"));
+                assert!(
+                    cause.starts_with("swap 'APP_NAME' turns entry 'APP_NAME/README.md'"),
+                    "{cause}"
+                );
+            }
+            res => panic!("expected an unsafe path, got {res:?}"),
+        }
+        assert!(!dest.exists());
+        assert!(!out.path().join("../outside").exists());
+    }
+
+    env::remove_var("BP_FOLDER");
+}